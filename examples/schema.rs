@@ -17,11 +17,17 @@ fn main() {
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
     export_schema(&schema_for!(ConfigResponse), &out_dir);
+    export_schema(&schema_for!(OwnershipProposalResponse), &out_dir);
+    export_schema(&schema_for!(PauseStatusResponse), &out_dir);
     export_schema(&schema_for!(StateResponse), &out_dir);
     export_schema(&schema_for!(AccruedRewardsResponse), &out_dir);
     export_schema(&schema_for!(HolderResponse), &out_dir);
     export_schema(&schema_for!(HoldersResponse), &out_dir);
+    export_schema(&schema_for!(StakedBalanceAtHeightResponse), &out_dir);
+    export_schema(&schema_for!(TotalStakedAtHeightResponse), &out_dir);
+    export_schema(&schema_for!(GroupDivergenceResponse), &out_dir);
     export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(Claim), &out_dir);
     export_schema(&schema_for!(ClaimsResponse), &out_dir);
+    export_schema(&schema_for!(AllClaimsResponse), &out_dir);
 }
@@ -1,82 +1,275 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{BlockInfo, CanonicalAddr, Deps, StdResult, Storage, Uint128};
-// use cw_storage_plus::Map;
+use crate::error::ContractError;
+use crate::state::{DEFAULT_LIMIT, MAX_LIMIT};
+use cosmwasm_std::{BlockInfo, CanonicalAddr, Deps, Order, StdResult, Storage, Timestamp, Uint128};
+use cw0::Duration;
 use cw20::Expiration;
-use cw_storage_plus::Map;
+use cw_storage_plus::{Bound, Item, Map, PrimaryKey, U64Key};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ClaimsResponse {
     pub claims: Vec<Claim>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllClaimsResponse {
+    pub claims: Vec<HolderClaim>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HolderClaim {
+    pub address: String,
+    pub claim: Claim,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Claim {
+    pub id: u64,
     pub amount: Uint128,
     pub release_at: Expiration,
+    pub created_at_height: u64,
+    /// Not set for the claims migrated from v3.0.0, their creation time is unknown
+    pub created_at_time: Option<Timestamp>,
+    /// Unbonding period in force when the claim was created
+    pub unbonding_period: Duration,
 }
 
-pub const CLAIM: Map<&[u8], Vec<Claim>> = Map::new("claims");
+pub const CLAIMS: Map<(&[u8], U64Key), Claim> = Map::new("holder_claims");
+pub const CLAIM_COUNT: Item<u64> = Item::new("claim_count");
+/// Number of outstanding claims of every holder
+pub const HOLDER_CLAIM_COUNTS: Map<&[u8], u32> = Map::new("holder_claim_counts");
 
 /// This creates a claim, such that the given address can claim an amount of tokens after
-/// the release date.
+/// the unbonding period. A claim releasing at the same expiration is increased instead.
+/// Returns the id of the created or increased claim.
 pub fn create_claim(
     storage: &mut dyn Storage,
     addr: CanonicalAddr,
     amount: Uint128,
-    release_at: Expiration,
-) -> StdResult<()> {
+    block: &BlockInfo,
+    unbonding_period: Duration,
+    max_claims: u32,
+) -> Result<u64, ContractError> {
+    let release_at = unbonding_period.after(block);
+    // releases grow with the claim ids while the unbonding period is unchanged,
+    // only the newest claim can release at the same expiration
+    let newest_claim = CLAIMS
+        .prefix(addr.as_slice())
+        .range(storage, None, None, Order::Descending)
+        .next()
+        .transpose()?
+        .map(|(_, claim)| claim);
+
+    if let Some(mut claim) = newest_claim.filter(|c| c.release_at == release_at) {
+        claim.amount += amount;
+        save_claim(storage, &addr, &claim)?;
+        return Ok(claim.id);
+    }
+
+    if count_claims(storage, &addr)? >= max_claims {
+        return Err(ContractError::TooManyClaims { max_claims });
+    }
+
     // add a claim to this user to get their tokens after the unbonding period
-    CLAIM.update(storage, addr.as_slice(), |old| -> StdResult<_> {
-        let mut claims = old.unwrap_or_default();
-        claims.push(Claim { amount, release_at });
-        Ok(claims)
-    })?;
-    Ok(())
+    let claim = Claim {
+        id: next_claim_id(storage)?,
+        amount,
+        release_at,
+        created_at_height: block.height,
+        created_at_time: Some(block.time),
+        unbonding_period,
+    };
+    save_claim(storage, &addr, &claim)?;
+    Ok(claim.id)
+}
+
+/// Claim ids are unique across holders, starting at 1
+pub fn next_claim_id(storage: &mut dyn Storage) -> StdResult<u64> {
+    let id = CLAIM_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    CLAIM_COUNT.save(storage, &id)?;
+    Ok(id)
+}
+
+/// Save the claim, or remove it once it is fully paid, and count the holder's claims
+pub fn save_claim(storage: &mut dyn Storage, addr: &CanonicalAddr, claim: &Claim) -> StdResult<()> {
+    let key = (addr.as_slice(), U64Key::new(claim.id));
+    let exists = CLAIMS.has(storage, key.clone());
+    let count = count_claims(storage, addr)?;
+    if claim.amount.is_zero() {
+        if exists {
+            CLAIMS.remove(storage, key);
+            HOLDER_CLAIM_COUNTS.save(storage, addr.as_slice(), &(count - 1))?;
+        }
+        return Ok(());
+    }
+    if !exists {
+        HOLDER_CLAIM_COUNTS.save(storage, addr.as_slice(), &(count + 1))?;
+    }
+    CLAIMS.save(storage, key, claim)
+}
+
+// claims of the address ordered by id, the oldest first in ascending order
+fn read_claims(storage: &dyn Storage, addr: &CanonicalAddr, order: Order) -> StdResult<Vec<Claim>> {
+    CLAIMS
+        .prefix(addr.as_slice())
+        .range(storage, None, None, order)
+        .map(|item| item.map(|(_, claim)| claim))
+        .collect()
+}
+
+fn count_claims(storage: &dyn Storage, addr: &CanonicalAddr) -> StdResult<u32> {
+    Ok(HOLDER_CLAIM_COUNTS
+        .may_load(storage, addr.as_slice())?
+        .unwrap_or_default())
 }
 
-/// This iterates over all mature claims for the address, and removes them, up to an optional cap.
+/// This iterates over the mature claims for the address, oldest first, and removes them,
+/// up to an optional cap. It stops at the first claim not mature yet, a claim created after
+/// the unbonding period was shortened is paid once the older claims are mature.
+/// The last mature claim is paid partially to reach the cap, the remainder is kept.
 /// it removes the finished claims and returns the total amount of tokens to be released.
-/*
-   TODO: claim stake need a Transfer WasmMsg::Execute in order
-    to transfer cw-20 from the staking contract to claimer address
-*/
 pub fn claim_tokens(
     storage: &mut dyn Storage,
     addr: CanonicalAddr,
     block: &BlockInfo,
     cap: Option<Uint128>,
-) -> StdResult<Uint128> {
+) -> Result<Uint128, ContractError> {
+    // only the claims needed to reach the cap are read
+    let mut claims = vec![];
+    let mut available = Uint128::zero();
+    for item in CLAIMS
+        .prefix(addr.as_slice())
+        .range(storage, None, None, Order::Ascending)
+    {
+        let (_, claim) = item?;
+        if !claim.release_at.is_expired(block) {
+            break;
+        }
+        available += claim.amount;
+        claims.push(claim);
+        if cap.is_some_and(|cap| available >= cap) {
+            break;
+        }
+    }
+
+    if cap == Some(Uint128::zero()) && !claims.is_empty() {
+        return Err(ContractError::ZeroCap {});
+    }
+
     let mut to_send = Uint128::zero();
-    CLAIM.update(storage, addr.as_slice(), |claim| -> StdResult<_> {
-        let (_send, waiting): (Vec<_>, _) =
-            claim.unwrap_or_default().iter().cloned().partition(|c| {
-                // if mature and we can pay fully, then include in _send
-                if c.release_at.is_expired(block) {
-                    if let Some(limit) = cap {
-                        if to_send + c.amount > limit {
-                            return false;
-                        }
-                    }
-                    // TODO: handle partial paying claims?
-                    to_send += c.amount;
-                    true
-                } else {
-                    // not to send, leave in waiting and save again
-                    false
-                }
-            });
-        Ok(waiting)
-    })?;
+    for mut c in claims {
+        let amount = match cap {
+            Some(limit) => std::cmp::min(c.amount, limit.checked_sub(to_send)?),
+            None => c.amount,
+        };
+        if amount.is_zero() {
+            break;
+        }
+        c.amount = c.amount.checked_sub(amount)?;
+        to_send += amount;
+        save_claim(storage, &addr, &c)?;
+    }
+
     Ok(to_send)
 }
 
-pub fn query_claims(deps: Deps, address: String) -> StdResult<ClaimsResponse> {
+/// This takes the given amount from the newest claims that are not mature yet,
+/// or all of them without an amount, and returns the total amount taken.
+pub fn rebond_claims(
+    storage: &mut dyn Storage,
+    addr: CanonicalAddr,
+    block: &BlockInfo,
+    amount: Option<Uint128>,
+) -> Result<Uint128, ContractError> {
+    // the newest claims come first
+    let claims = read_claims(storage, &addr, Order::Descending)?;
+
+    let available: Uint128 = claims
+        .iter()
+        .filter(|c| !c.release_at.is_expired(block))
+        .map(|c| c.amount)
+        .sum();
+    if available.is_zero() {
+        return Err(ContractError::NothingToRebond {});
+    }
+    let amount = amount.unwrap_or(available);
+    if amount > available {
+        return Err(ContractError::InsufficientClaims { available });
+    }
+
+    let mut remaining = amount;
+    for mut c in claims {
+        if remaining.is_zero() {
+            break;
+        }
+        if c.release_at.is_expired(block) {
+            continue;
+        }
+        let take = std::cmp::min(c.amount, remaining);
+        c.amount = c.amount.checked_sub(take)?;
+        remaining = remaining.checked_sub(take)?;
+        save_claim(storage, &addr, &c)?;
+    }
+
+    Ok(amount)
+}
+
+pub fn query_claims(
+    deps: Deps,
+    address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ClaimsResponse> {
     let address_raw = deps.api.addr_canonicalize(address.as_str())?;
 
-    let claims = CLAIM
-        .may_load(deps.storage, address_raw.as_slice())?
-        .unwrap_or_default();
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive_int);
+
+    let claims = CLAIMS
+        .prefix(address_raw.as_slice())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, claim)| claim))
+        .collect::<StdResult<Vec<Claim>>>()?;
     Ok(ClaimsResponse { claims })
 }
+
+/// Claims of every holder ordered by address then id,
+/// paginated with the address and id of the last returned claim
+pub fn query_all_claims(
+    deps: Deps,
+    start_after: Option<(String, u64)>,
+    limit: Option<u32>,
+) -> StdResult<AllClaimsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        Some((address, id)) => {
+            let address_raw = deps.api.addr_canonicalize(address.as_str())?;
+            let key = (address_raw.as_slice(), U64Key::new(id)).joined_key();
+            Some(Bound::exclusive(key))
+        }
+        None => None,
+    };
+
+    let claims = CLAIMS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (key, claim) = item?;
+            let address = deps.api.addr_humanize(&claim_key_address(&key))?;
+            Ok(HolderClaim {
+                address: address.to_string(),
+                claim,
+            })
+        })
+        .collect::<StdResult<Vec<HolderClaim>>>()?;
+    Ok(AllClaimsResponse { claims })
+}
+
+// the address of a raw claim key, stored with a two bytes length prefix before the claim id
+fn claim_key_address(key: &[u8]) -> CanonicalAddr {
+    let len = u16::from_be_bytes([key[0], key[1]]) as usize;
+    CanonicalAddr::from(key[2..2 + len].to_vec())
+}
@@ -1,66 +1,246 @@
 use crate::global::handle_update_global_index;
-use crate::state::{Config, State, CONFIG, STATE};
+use crate::owner::{
+    handle_accept_ownership, handle_cancel_ownership_proposal, handle_propose_new_owner,
+    handle_update_config, query_ownership_proposal, validate_max_claims, validate_penalty,
+    validate_penalty_recipient, validate_reward_denoms,
+};
+use crate::pause::{assert_not_paused, handle_pause, handle_unpause, query_pause_status};
+use crate::state::{
+    Config, PauseInfo, RewardIndex, State, CONFIG, DEFAULT_MAX_CLAIMS, PAUSE_INFO, STATE,
+    TOTAL_WEIGHT,
+};
 use crate::user::{
-    handle_claim_rewards, handle_receive, handle_unbound, handle_withdraw_stake,
-    query_accrued_rewards, query_holder, query_holders,
+    handle_claim_rewards, handle_compound_rewards, handle_instant_unbond, handle_rebond,
+    handle_receive, handle_transfer_stake, handle_unbound, handle_withdraw_stake,
+    query_accrued_rewards, query_holder, query_holders, query_staked_balance_at_height,
+    query_total_staked_at_height,
 };
 use cosmwasm_std::{
-    entry_point, to_binary, Binary, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdResult,
-    Uint128,
+    entry_point, to_binary, Binary, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdError,
+    StdResult, Uint128,
+};
+
+use crate::claim::{query_all_claims, query_claims};
+use crate::error::ContractError;
+use crate::group::{
+    handle_sync_group, handle_update_group, query_admin, query_group_divergence, query_hooks,
+    query_list_members, query_member, query_total_weight, validate_weight_function,
+};
+use crate::migration::{assert_migrated, handle_continue_migration, migrate_from_v3};
+use crate::msg::{
+    ConfigResponse, ExecuteMsg, InstantiateMsg, MigrateMsg, Operation, QueryMsg, StateResponse,
 };
+use cw2::{set_contract_version, CONTRACT};
+use semver::Version;
 
-use crate::claim::query_claims;
-use crate::msg::{ConfigResponse, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, StateResponse};
+// version info for migration info
+const CONTRACT_NAME: &str = "crates.io:loterra-staking-contract";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    validate_reward_denoms(&msg.reward_denoms)?;
+    if let Some(penalty) = msg.instant_unbond_penalty {
+        validate_penalty(penalty)?;
+    }
+    let max_claims = msg.max_claims.unwrap_or(DEFAULT_MAX_CLAIMS);
+    validate_max_claims(max_claims)?;
+    let weight_function = msg.weight_function.unwrap_or_default();
+    validate_weight_function(&weight_function)?;
+
     let conf = Config {
-        cw20_token_addr: deps.api.addr_canonicalize(&msg.cw20_token_addr.as_str())?,
-        reward_denom: msg.reward_denom,
+        owner: deps.api.addr_canonicalize(info.sender.as_str())?,
+        cw20_token_addr: deps.api.addr_canonicalize(msg.cw20_token_addr.as_str())?,
+        reward_denoms: msg.reward_denoms.clone(),
+        reward_token: msg
+            .reward_token
+            .as_ref()
+            .map(|token| deps.api.addr_canonicalize(token.as_str()))
+            .transpose()?,
         unbonding_period: msg.unbonding_period,
-        group_addr: deps.api.addr_canonicalize(&msg.group_addr.as_str())?,
+        group_addr: msg
+            .group_addr
+            .map(|group_addr| deps.api.addr_canonicalize(group_addr.as_str()))
+            .transpose()?,
+        guardian: msg
+            .guardian
+            .map(|guardian| deps.api.addr_canonicalize(guardian.as_str()))
+            .transpose()?,
+        // a zero period releases rewards at once
+        distribution_period: msg.distribution_period.filter(|period| *period > 0),
+        instant_unbond_penalty: msg.instant_unbond_penalty,
+        treasury: msg
+            .treasury
+            .map(|treasury| deps.api.addr_canonicalize(treasury.as_str()))
+            .transpose()?,
+        max_claims,
+        weight_function,
     };
+    validate_penalty_recipient(&conf)?;
 
     CONFIG.save(deps.storage, &conf)?;
     STATE.save(
         deps.storage,
         &State {
-            global_index: Decimal::zero(),
             total_balance: Uint128::zero(),
-            prev_reward_balance: Uint128::zero(),
+            total_unbonding: Uint128::zero(),
+            reward_indices: msg
+                .reward_denoms
+                .into_iter()
+                .chain(msg.reward_token.map(|token| token.to_string()))
+                .map(|denom| RewardIndex {
+                    denom,
+                    global_index: Decimal::zero(),
+                    prev_reward_balance: Uint128::zero(),
+                    undistributed_rewards: Uint128::zero(),
+                })
+                .collect(),
         },
     )?;
+    PAUSE_INFO.save(deps.storage, &PauseInfo::default())?;
+    TOTAL_WEIGHT.save(deps.storage, &0)?;
 
     Ok(Response::default())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    // only the migration can go on until the v3.0.0 storage is fully migrated
+    if !matches!(msg, ExecuteMsg::ContinueMigration { .. }) {
+        assert_migrated(deps.storage)?;
+    }
+
     match msg {
-        ExecuteMsg::ClaimRewards { recipient } => handle_claim_rewards(deps, env, info, recipient),
+        ExecuteMsg::ClaimRewards { recipient } => {
+            assert_not_paused(deps.storage, Operation::Claim)?;
+            handle_claim_rewards(deps, env, info, recipient)
+        }
+        ExecuteMsg::CompoundRewards {} => {
+            assert_not_paused(deps.storage, Operation::Claim)?;
+            assert_not_paused(deps.storage, Operation::Bond)?;
+            handle_compound_rewards(deps, env, info)
+        }
         ExecuteMsg::UpdateGlobalIndex {} => handle_update_global_index(deps, env),
-        ExecuteMsg::UnbondStake { amount } => handle_unbound(deps, env, info, amount),
-        ExecuteMsg::WithdrawStake { cap } => handle_withdraw_stake(deps, env, info, cap),
+        ExecuteMsg::UpdateConfig {
+            unbonding_period,
+            reward_denoms,
+            reward_token,
+            guardian,
+            distribution_period,
+            instant_unbond_penalty,
+            disable_instant_unbond,
+            treasury,
+            remove_treasury,
+            max_claims,
+        } => handle_update_config(
+            deps,
+            env,
+            info,
+            unbonding_period,
+            reward_denoms,
+            reward_token,
+            guardian,
+            distribution_period,
+            instant_unbond_penalty,
+            disable_instant_unbond,
+            treasury,
+            remove_treasury,
+            max_claims,
+        ),
+        ExecuteMsg::ProposeNewOwner { owner, expires } => {
+            handle_propose_new_owner(deps, env, info, owner, expires)
+        }
+        ExecuteMsg::AcceptOwnership {} => handle_accept_ownership(deps, env, info),
+        ExecuteMsg::CancelOwnershipProposal {} => handle_cancel_ownership_proposal(deps, info),
+        ExecuteMsg::Pause { operations } => handle_pause(deps, info, operations),
+        ExecuteMsg::Unpause { operations } => handle_unpause(deps, info, operations),
+        ExecuteMsg::UpdateGroup { group_addr } => handle_update_group(deps, info, group_addr),
+        ExecuteMsg::SyncGroup { start_after, limit } => {
+            handle_sync_group(deps, info, start_after, limit)
+        }
+        ExecuteMsg::ContinueMigration { limit } => {
+            handle_continue_migration(deps, env, info, limit)
+        }
+        ExecuteMsg::UnbondStake { amount } => {
+            assert_not_paused(deps.storage, Operation::Unbond)?;
+            handle_unbound(deps, env, info, amount)
+        }
+        ExecuteMsg::InstantUnbond { amount } => {
+            assert_not_paused(deps.storage, Operation::Unbond)?;
+            assert_not_paused(deps.storage, Operation::Withdraw)?;
+            handle_instant_unbond(deps, env, info, amount)
+        }
+        ExecuteMsg::Rebond { amount } => {
+            assert_not_paused(deps.storage, Operation::Bond)?;
+            handle_rebond(deps, env, info, amount)
+        }
+        ExecuteMsg::TransferStake { recipient, amount } => {
+            assert_not_paused(deps.storage, Operation::Unbond)?;
+            assert_not_paused(deps.storage, Operation::Bond)?;
+            handle_transfer_stake(deps, env, info, recipient, amount)
+        }
+        ExecuteMsg::WithdrawStake { cap, recipient } => {
+            assert_not_paused(deps.storage, Operation::Withdraw)?;
+            handle_withdraw_stake(deps, env, info, cap, recipient)
+        }
         ExecuteMsg::Receive(msg) => handle_receive(deps, env, info, msg),
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    // holders and claims are partly in the v3.0.0 layout until the migration is complete
+    if !matches!(msg, QueryMsg::Config {}) {
+        assert_migrated(deps.storage).map_err(|err| StdError::generic_err(err.to_string()))?;
+    }
+
     match msg {
-        QueryMsg::Config {} => to_binary(&query_config(deps, _env, msg)?),
-        QueryMsg::State {} => to_binary(&query_state(deps, _env, msg)?),
-        QueryMsg::AccruedRewards { address } => to_binary(&query_accrued_rewards(deps, address)?),
+        QueryMsg::Config {} => to_binary(&query_config(deps, env, msg)?),
+        QueryMsg::State {} => to_binary(&query_state(deps, env, msg)?),
+        QueryMsg::AccruedRewards { address } => {
+            to_binary(&query_accrued_rewards(deps, env, address)?)
+        }
         QueryMsg::Holder { address } => to_binary(&query_holder(deps, address)?),
         QueryMsg::Holders { start_after, limit } => {
             to_binary(&query_holders(deps, start_after, limit)?)
         }
-        QueryMsg::Claims { address } => to_binary(&query_claims(deps, address)?),
+        QueryMsg::Claims {
+            address,
+            start_after,
+            limit,
+        } => to_binary(&query_claims(deps, address, start_after, limit)?),
+        QueryMsg::AllClaims { start_after, limit } => {
+            to_binary(&query_all_claims(deps, start_after, limit)?)
+        }
+        QueryMsg::OwnershipProposal {} => to_binary(&query_ownership_proposal(deps)?),
+        QueryMsg::PauseStatus {} => to_binary(&query_pause_status(deps)?),
+        QueryMsg::StakedBalanceAtHeight { address, height } => {
+            to_binary(&query_staked_balance_at_height(deps, env, address, height)?)
+        }
+        QueryMsg::TotalStakedAtHeight { height } => {
+            to_binary(&query_total_staked_at_height(deps, env, height)?)
+        }
+        QueryMsg::Member { addr, at_height } => to_binary(&query_member(deps, addr, at_height)?),
+        QueryMsg::ListMembers { start_after, limit } => {
+            to_binary(&query_list_members(deps, start_after, limit)?)
+        }
+        QueryMsg::TotalWeight {} => to_binary(&query_total_weight(deps)?),
+        QueryMsg::Admin {} => to_binary(&query_admin(deps)?),
+        QueryMsg::Hooks {} => to_binary(&query_hooks()?),
+        QueryMsg::GroupDivergence { start_after, limit } => {
+            to_binary(&query_group_divergence(deps, start_after, limit)?)
+        }
     }
 }
 
@@ -68,21 +248,74 @@ pub fn query_config(deps: Deps, _env: Env, _msg: QueryMsg) -> StdResult<ConfigRe
     let config = CONFIG.load(deps.storage)?;
 
     Ok(ConfigResponse {
+        owner: deps.api.addr_humanize(&config.owner)?.to_string(),
+        guardian: config
+            .guardian
+            .map(|guardian| deps.api.addr_humanize(&guardian))
+            .transpose()?
+            .map(|guardian| guardian.to_string()),
         cw20_token_addr: deps.api.addr_humanize(&config.cw20_token_addr)?.to_string(),
-        reward_denom: config.reward_denom,
+        reward_denoms: config.reward_denoms,
+        reward_token: config
+            .reward_token
+            .map(|token| deps.api.addr_humanize(&token))
+            .transpose()?
+            .map(|token| token.to_string()),
         unbonding_period: config.unbonding_period,
+        distribution_period: config.distribution_period,
+        instant_unbond_penalty: config.instant_unbond_penalty,
+        treasury: config
+            .treasury
+            .map(|treasury| deps.api.addr_humanize(&treasury))
+            .transpose()?
+            .map(|treasury| treasury.to_string()),
+        max_claims: config.max_claims,
+        weight_function: config.weight_function,
+        group_addr: config
+            .group_addr
+            .map(|group_addr| deps.api.addr_humanize(&group_addr))
+            .transpose()?
+            .map(|group_addr| group_addr.to_string()),
     })
 }
 pub fn query_state(deps: Deps, _env: Env, _msg: QueryMsg) -> StdResult<StateResponse> {
     let state = STATE.load(deps.storage)?;
     Ok(StateResponse {
-        global_index: state.global_index,
         total_balance: state.total_balance,
-        prev_reward_balance: state.prev_reward_balance,
+        total_unbonding: state.total_unbonding,
+        reward_indices: state.reward_indices,
     })
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
-    Ok(Response::default())
+pub fn migrate(mut deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let previous_version = match CONTRACT.may_load(deps.storage)? {
+        Some(stored) => {
+            if stored.contract != CONTRACT_NAME {
+                return Err(ContractError::CannotMigrate {
+                    previous_contract: stored.contract,
+                });
+            }
+            let previous: Version = stored.version.parse()?;
+            let current: Version = CONTRACT_VERSION.parse()?;
+            if previous > current {
+                return Err(ContractError::CannotMigrateVersion {
+                    previous_version: stored.version,
+                });
+            }
+            stored.version
+        }
+        // v3.0.0 was deployed before the contract version was recorded
+        None => {
+            migrate_from_v3(deps.branch(), &env, &msg)?;
+            "3.0.0".to_string()
+        }
+    };
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("previous_version", previous_version)
+        .add_attribute("new_version", CONTRACT_VERSION))
 }
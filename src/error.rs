@@ -0,0 +1,122 @@
+use cosmwasm_std::{OverflowError, StdError, Uint128};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
+    #[error("Semver parsing error: {0}")]
+    SemVer(String),
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    #[error("Only the staking token contract can send receive messages")]
+    InvalidCw20Token {},
+
+    #[error("Only the cw20 reward token can be distributed")]
+    InvalidRewardToken {},
+
+    #[error("Do not send funds with stake")]
+    FundsNotAllowed {},
+
+    #[error("Amount required")]
+    ZeroAmount {},
+
+    #[error("Decrease amount cannot exceed user balance: {available}")]
+    InsufficientStake { available: Uint128 },
+
+    #[error("No cw4 group attached")]
+    NoGroup {},
+
+    #[error("Weight scaling factor and max weight must be greater than zero")]
+    InvalidWeightFunction {},
+
+    #[error("Cannot transfer stake to the same address")]
+    SelfTransfer {},
+
+    #[error("Max claims must be greater than zero")]
+    InvalidMaxClaims {},
+
+    #[error("Too many outstanding claims, max: {max_claims}")]
+    TooManyClaims { max_claims: u32 },
+
+    #[error("No unbonding claims to rebond")]
+    NothingToRebond {},
+
+    #[error("Rebond amount cannot exceed unbonding claims: {available}")]
+    InsufficientClaims { available: Uint128 },
+
+    #[error("No rewards have accrued yet")]
+    NoRewards {},
+
+    #[error("Instant unbond is disabled")]
+    InstantUnbondDisabled {},
+
+    #[error("Instant unbond penalty must be lower than 100%")]
+    InvalidPenalty {},
+
+    #[error("Cannot set and unset {field} in the same update")]
+    ConflictingUpdate { field: String },
+
+    #[error("Set a treasury or pay rewards in the staking token to collect the penalty")]
+    NoPenaltyRecipient {},
+
+    #[error("Rewards are not paid in the staking token")]
+    CompoundNotSupported {},
+
+    #[error("Wait for the unbonding period")]
+    NothingToWithdraw {},
+
+    #[error("Cap must be greater than zero to withdraw mature claims")]
+    ZeroCap {},
+
+    #[error("Operation is paused")]
+    Paused {},
+
+    #[error("New owner cannot be the current owner")]
+    SameOwner {},
+
+    #[error("Proposal expiration already passed")]
+    InvalidExpiration {},
+
+    #[error("No ownership proposal found")]
+    NoOwnershipProposal {},
+
+    #[error("Ownership proposal expired")]
+    OwnershipProposalExpired {},
+
+    #[error("Cannot migrate from a different contract: {previous_contract}")]
+    CannotMigrate { previous_contract: String },
+
+    #[error("Cannot migrate from a newer version: {previous_version}")]
+    CannotMigrateVersion { previous_version: String },
+
+    #[error("Reward denom listed more than once: {denom}")]
+    DuplicateRewardDenom { denom: String },
+
+    #[error("Reward denom cannot be removed: {denom}")]
+    RewardDenomRemoved { denom: String },
+
+    #[error("Reward token is already set")]
+    RewardTokenAlreadySet {},
+
+    #[error("Migrating a v3.0.0 contract requires an owner")]
+    MigrationOwnerRequired {},
+
+    #[error("The v3.0.0 holders and claims are still being migrated")]
+    MigrationPending {},
+
+    #[error("No pending migration")]
+    NoPendingMigration {},
+}
+
+impl From<semver::Error> for ContractError {
+    fn from(err: semver::Error) -> Self {
+        Self::SemVer(err.to_string())
+    }
+}
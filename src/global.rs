@@ -1,48 +1,256 @@
-use crate::state::{CONFIG, STATE};
+use crate::state::{
+    reward_token_denom, Config, RewardIndex, RewardStream, State, CONFIG, REWARD_STREAMS, STATE,
+};
 
-use crate::math::decimal_summation_in_256;
-use cosmwasm_std::{Decimal, DepsMut, Env, Response, StdError, StdResult};
+use crate::error::ContractError;
+use crate::math::{decimal_multiplication_in_256, decimal_summation_in_256};
+use cosmwasm_std::{Coin, Decimal, DepsMut, Env, Response, StdResult, Storage, Uint128};
+use std::cmp::min;
 
-/// Increase global_index according to claimed rewards amount
-/// Only hub_contract is allowed to execute
-pub fn handle_update_global_index(deps: DepsMut, env: Env) -> StdResult<Response> {
+/// Increase the global_index of every reward denom according to claimed rewards amount
+/// Anybody can execute, user actions also update the global index on their own
+pub fn handle_update_global_index(mut deps: DepsMut, env: Env) -> Result<Response, ContractError> {
     let mut state = STATE.load(deps.storage)?;
-    // anybody can trigger update_global_index
-    /*
-    if config.lottery_contract != deps.api.canonical_address(&env.message.sender)? {
-        return Err(StdError::unauthorized());
+    let config = CONFIG.load(deps.storage)?;
+
+    let claimed_rewards = update_global_index(deps.branch(), &env, &config, &mut state)?;
+
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_global_index")
+        .add_attribute("claimed_rewards", coins_to_string(&claimed_rewards)))
+}
+
+/// Increase the reward token global_index according to the deposited amount
+/// Only the cw20 reward token is allowed to execute, through a receive message
+pub fn handle_distribute_rewards(
+    deps: DepsMut,
+    env: Env,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+
+    if amount.is_zero() {
+        return Err(ContractError::ZeroAmount {});
+    }
+
+    let now = env.block.time.seconds();
+    advance_reward_streams(deps.storage, &mut state, now)?;
+
+    let reward_token = reward_token_denom(deps.api, &config)?;
+    let total_balance = state.total_balance;
+    let reward_index = state
+        .reward_indices
+        .iter_mut()
+        .find(|reward_index| Some(&reward_index.denom) == reward_token.as_ref())
+        .ok_or(ContractError::InvalidRewardToken {})?;
+
+    // the deposited amount is tracked like a native balance
+    reward_index.prev_reward_balance += amount;
+
+    add_rewards(
+        deps.storage,
+        config.distribution_period,
+        total_balance,
+        reward_index,
+        amount,
+        now,
+    )?;
+
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "distribute_rewards")
+        .add_attribute("rewards", amount))
+}
+
+/// Index the native rewards received since the last update and the rewards streamed until now
+/// Return the received rewards, the caller is responsible for saving the state
+pub fn update_global_index(
+    deps: DepsMut,
+    env: &Env,
+    config: &Config,
+    state: &mut State,
+) -> StdResult<Vec<Coin>> {
+    let reward_token = reward_token_denom(deps.api, config)?;
+    let now = env.block.time.seconds();
+
+    advance_reward_streams(deps.storage, state, now)?;
+
+    // Load the reward contract balances
+    let balances = deps
+        .querier
+        .query_all_balances(env.contract.address.clone())?;
+
+    let total_balance = state.total_balance;
+    let mut claimed_rewards: Vec<Coin> = vec![];
+    for reward_index in state.reward_indices.iter_mut() {
+        // cw20 rewards are indexed when they are deposited,
+        // only the rewards held while nothing was bonded are left
+        if Some(&reward_index.denom) == reward_token.as_ref() {
+            add_rewards(
+                deps.storage,
+                config.distribution_period,
+                total_balance,
+                reward_index,
+                Uint128::zero(),
+                now,
+            )?;
+            continue;
+        }
+
+        let balance = balances
+            .iter()
+            .find(|coin| coin.denom == reward_index.denom)
+            .map(|coin| coin.amount)
+            .unwrap_or_default();
+
+        // claimed_rewards = current_balance - prev_balance;
+        let claimed = balance.checked_sub(reward_index.prev_reward_balance)?;
+
+        reward_index.prev_reward_balance = balance;
+
+        add_rewards(
+            deps.storage,
+            config.distribution_period,
+            total_balance,
+            reward_index,
+            claimed,
+            now,
+        )?;
+
+        if !claimed.is_zero() {
+            claimed_rewards.push(Coin {
+                denom: reward_index.denom.clone(),
+                amount: claimed,
+            });
+        }
+    }
+
+    Ok(claimed_rewards)
+}
+
+/// Move the rewards streamed since the last update into the global_index of every denom
+/// The caller is responsible for saving the state
+pub fn advance_reward_streams(
+    storage: &mut dyn Storage,
+    state: &mut State,
+    now: u64,
+) -> StdResult<()> {
+    for (denom, stream) in release_streamed_rewards(storage, state, now)? {
+        REWARD_STREAMS.save(storage, &denom, &stream)?;
     }
-     */
+    Ok(())
+}
 
-    // Zero staking balance check
+// increase the global indices by the rewards streamed until now
+// and return the streams to save, queries use it without saving
+pub fn release_streamed_rewards(
+    storage: &dyn Storage,
+    state: &mut State,
+    now: u64,
+) -> StdResult<Vec<(String, RewardStream)>> {
+    // streams hold their rewards while nothing is bonded
     if state.total_balance.is_zero() {
-        return Err(StdError::generic_err("No asset is bonded by Hub"));
+        return Ok(vec![]);
     }
 
-    let reward_denom = CONFIG.load(deps.storage)?.reward_denom;
+    let mut streams = vec![];
+    for reward_index in state.reward_indices.iter_mut() {
+        let mut stream = match REWARD_STREAMS.may_load(storage, &reward_index.denom)? {
+            Some(stream) => stream,
+            None => continue,
+        };
+        let until = min(now, stream.period_finish);
+        if until <= stream.last_update_time {
+            continue;
+        }
 
-    // Load the reward contract balance
-    let balance = deps
-        .querier
-        .query_balance(env.contract.address, reward_denom.as_str())
-        .unwrap();
+        // global_index += reward_rate * elapsed / total_balance;
+        let released = decimal_multiplication_in_256(
+            stream.reward_rate,
+            Decimal::from_ratio(until - stream.last_update_time, 1u64),
+        );
+        reward_index.global_index =
+            decimal_summation_in_256(reward_index.global_index, released / state.total_balance);
 
-    let previous_balance = state.prev_reward_balance;
+        stream.last_update_time = until;
+        streams.push((reward_index.denom.clone(), stream));
+    }
+    Ok(streams)
+}
 
-    // claimed_rewards = current_balance - prev_balance;
-    let claimed_rewards = balance.amount.checked_sub(previous_balance)?;
+// index new rewards at once, or spread them with the unreleased rewards
+// over a new distribution period. Streams must be advanced to now first.
+// Rewards are held as undistributed while nothing is bonded.
+// A top up that would slow down the running stream is spread over its
+// remaining time instead, so that dust deposits cannot delay the rewards.
+pub fn add_rewards(
+    storage: &mut dyn Storage,
+    distribution_period: Option<u64>,
+    total_balance: Uint128,
+    reward_index: &mut RewardIndex,
+    amount: Uint128,
+    now: u64,
+) -> StdResult<()> {
+    if total_balance.is_zero() {
+        reward_index.undistributed_rewards += amount;
+        return Ok(());
+    }
 
-    state.prev_reward_balance = balance.amount;
+    let amount = amount + std::mem::take(&mut reward_index.undistributed_rewards);
+    if amount.is_zero() {
+        return Ok(());
+    }
 
-    // global_index += claimed_rewards / total_balance;
-    state.global_index = decimal_summation_in_256(
-        state.global_index,
-        Decimal::from_ratio(claimed_rewards, state.total_balance),
-    );
+    let period = match distribution_period {
+        Some(period) => period,
+        None => {
+            // global_index += rewards / total_balance;
+            reward_index.global_index = decimal_summation_in_256(
+                reward_index.global_index,
+                Decimal::from_ratio(amount, total_balance),
+            );
+            return Ok(());
+        }
+    };
 
-    STATE.save(deps.storage, &state)?;
+    let mut stream = REWARD_STREAMS
+        .may_load(storage, &reward_index.denom)?
+        .unwrap_or_default();
+    let leftover = if now < stream.period_finish {
+        decimal_multiplication_in_256(
+            stream.reward_rate,
+            Decimal::from_ratio(stream.period_finish - stream.last_update_time, 1u64),
+        )
+    } else {
+        Decimal::zero()
+    };
 
-    Ok(Response::new()
-        .add_attribute("action", "update_global_index")
-        .add_attribute("claimed_rewards", claimed_rewards))
+    // reward_rate = (new_rewards + unreleased_rewards) / distribution_period;
+    let reward_rate = decimal_summation_in_256(leftover, Decimal::from_ratio(amount, 1u64))
+        / Uint128::from(period);
+    if now < stream.period_finish && reward_rate < stream.reward_rate {
+        // reward_rate += new_rewards / remaining_time;
+        stream.reward_rate = decimal_summation_in_256(
+            stream.reward_rate,
+            Decimal::from_ratio(amount, stream.period_finish - now),
+        );
+    } else {
+        stream.reward_rate = reward_rate;
+        stream.period_finish = now + period;
+    }
+    stream.last_update_time = now;
+    REWARD_STREAMS.save(storage, &reward_index.denom, &stream)
+}
+
+// format coins as a comma separated list for the event attributes
+pub fn coins_to_string(coins: &[Coin]) -> String {
+    coins
+        .iter()
+        .map(|coin| coin.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
 }
@@ -0,0 +1,234 @@
+use crate::error::ContractError;
+use crate::math::integer_sqrt;
+use crate::msg::{GroupDivergenceResponse, WeightDivergence};
+use crate::state::{
+    calc_range_start, Config, WeightFunction, CONFIG, DEFAULT_LIMIT, MAX_LIMIT, MEMBERS,
+    PREFIXED_HOLDERS, TOTAL_WEIGHT,
+};
+
+use cosmwasm_std::{
+    to_binary, Addr, CanonicalAddr, CosmosMsg, Deps, DepsMut, MessageInfo, Order, Response,
+    StdError, StdResult, Uint128, Uint64, WasmMsg,
+};
+use cw4::{
+    AdminResponse, Cw4QueryMsg, HooksResponse, Member, MemberListResponse, MemberResponse,
+    TotalWeightResponse,
+};
+use cw_storage_plus::Bound;
+use std::cmp::min;
+use std::convert::TryFrom;
+
+/// Voting weight of a staked balance, the same one mirrored into the cw4 group
+/// Fails instead of truncating a weight that does not fit in a u64
+pub fn member_weight(weight_function: &WeightFunction, balance: Uint128) -> StdResult<u64> {
+    let mut weight = balance / weight_function.scaling_factor;
+    if weight_function.square_root {
+        weight = Uint128::from(integer_sqrt(weight.u128()));
+    }
+    if let Some(max_weight) = weight_function.max_weight {
+        weight = min(weight, Uint128::from(max_weight));
+    }
+    Ok(Uint64::try_from(weight)?.u64())
+}
+
+/// Attach a cw4 group to mirror the member weights, or detach it without an address
+/// Only the owner is allowed to execute
+pub fn handle_update_group(
+    deps: DepsMut,
+    info: MessageInfo,
+    group_addr: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let group_addr = group_addr
+        .map(|group_addr| deps.api.addr_validate(&group_addr))
+        .transpose()?;
+    config.group_addr = group_addr
+        .as_ref()
+        .map(|group_addr| deps.api.addr_canonicalize(group_addr.as_str()))
+        .transpose()?;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_group")
+        .add_attribute(
+            "group_addr",
+            group_addr.map_or("none".to_string(), |group_addr| group_addr.to_string()),
+        ))
+}
+
+/// Set the group weights of a page of holders from their staked balance
+/// Only the owner is allowed to execute
+pub fn handle_sync_group(
+    deps: DepsMut,
+    info: MessageInfo,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let group_addr = config
+        .group_addr
+        .as_ref()
+        .ok_or(ContractError::NoGroup {})?;
+
+    let holder_weights = read_holder_weights(deps.as_ref(), &config, start_after, limit)?;
+    let synced = holder_weights.len();
+    let last_holder = holder_weights
+        .last()
+        .map(|(address, _)| address.clone())
+        .unwrap_or_default();
+
+    let mut add = vec![];
+    let mut remove = vec![];
+    for (address, weight) in holder_weights {
+        if weight == 0 {
+            remove.push(address);
+        } else {
+            add.push(Member {
+                addr: address,
+                weight,
+            });
+        }
+    }
+
+    let mut res = Response::new();
+    if synced > 0 {
+        res = res.add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: deps.api.addr_humanize(group_addr)?.to_string(),
+            msg: to_binary(&cw4_group::msg::ExecuteMsg::UpdateMembers { remove, add })?,
+            funds: vec![],
+        }));
+    }
+
+    Ok(res
+        .add_attribute("action", "sync_group")
+        .add_attribute("synced", synced.to_string())
+        .add_attribute("last_holder", last_holder))
+}
+
+pub fn validate_weight_function(weight_function: &WeightFunction) -> Result<(), ContractError> {
+    if weight_function.scaling_factor.is_zero() || weight_function.max_weight == Some(0) {
+        return Err(ContractError::InvalidWeightFunction {});
+    }
+    Ok(())
+}
+
+/// cw4 member query, holders without weight are not members
+pub fn query_member(deps: Deps, addr: String, at_height: Option<u64>) -> StdResult<MemberResponse> {
+    let addr = deps.api.addr_validate(&addr)?;
+    let weight = match at_height {
+        Some(height) => MEMBERS.may_load_at_height(deps.storage, &addr, height)?,
+        None => MEMBERS.may_load(deps.storage, &addr)?,
+    };
+    Ok(MemberResponse { weight })
+}
+
+pub fn query_list_members(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<MemberListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let start = start_after.map(|addr| Bound::exclusive(addr.as_str()));
+
+    let members = MEMBERS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (key, weight) = item?;
+            Ok(Member {
+                addr: String::from_utf8(key)?,
+                weight,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(MemberListResponse { members })
+}
+
+pub fn query_total_weight(deps: Deps) -> StdResult<TotalWeightResponse> {
+    Ok(TotalWeightResponse {
+        weight: TOTAL_WEIGHT.load(deps.storage)?,
+    })
+}
+
+/// The contract owner administers the stake based group
+pub fn query_admin(deps: Deps) -> StdResult<AdminResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    Ok(AdminResponse {
+        admin: Some(deps.api.addr_humanize(&config.owner)?.to_string()),
+    })
+}
+
+pub fn query_group_divergence(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<GroupDivergenceResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let group_addr = match config.group_addr.as_ref() {
+        Some(group_addr) => deps.api.addr_humanize(group_addr)?,
+        None => return Err(StdError::generic_err(ContractError::NoGroup {}.to_string())),
+    };
+
+    let holder_weights = read_holder_weights(deps, &config, start_after, limit)?;
+    let last_holder = holder_weights.last().map(|(address, _)| address.clone());
+
+    let mut divergences = vec![];
+    for (address, weight) in holder_weights {
+        let member: MemberResponse = deps.querier.query_wasm_smart(
+            group_addr.as_str(),
+            &Cw4QueryMsg::Member {
+                addr: address.clone(),
+                at_height: None,
+            },
+        )?;
+        let weight = Some(weight).filter(|weight| *weight > 0);
+        if member.weight != weight {
+            divergences.push(WeightDivergence {
+                address,
+                weight,
+                group_weight: member.weight,
+            });
+        }
+    }
+    Ok(GroupDivergenceResponse {
+        divergences,
+        last_holder,
+    })
+}
+
+// a page of holders in address order with the weight of their staked balance
+fn read_holder_weights(
+    deps: Deps,
+    config: &Config,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Vec<(String, u64)>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = calc_range_start(deps.api, start_after.map(Addr::unchecked))?.map(Bound::exclusive);
+
+    PREFIXED_HOLDERS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (key, holder) = item?;
+            let address = deps.api.addr_humanize(&CanonicalAddr::from(key))?;
+            let weight = member_weight(&config.weight_function, holder.balance)?;
+            Ok((address.to_string(), weight))
+        })
+        .collect()
+}
+
+/// Membership changes are not reported to hooks
+pub fn query_hooks() -> StdResult<HooksResponse> {
+    Ok(HooksResponse { hooks: vec![] })
+}
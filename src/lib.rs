@@ -1,8 +1,13 @@
 pub mod contract;
+pub mod error;
 pub mod state;
 
 mod global;
+mod group;
 mod math;
+mod migration;
+mod owner;
+mod pause;
 mod taxation;
 mod user;
 
@@ -25,6 +25,21 @@ pub fn decimal_subtraction_in_256(a: Decimal, b: Decimal) -> Decimal {
     c_u256
 }
 
+/// return the largest integer whose square is lower or equal to value
+pub fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    // Newton's method, decreasing from an estimate above the root
+    let mut x = value;
+    let mut y = value / 2 + value % 2;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,4 +94,13 @@ mod tests {
         let res = decimal_subtraction_in_256(a, b);
         assert_eq!(res.to_string(), "0.2");
     }
+
+    #[test]
+    fn test_integer_sqrt() {
+        assert_eq!(integer_sqrt(0), 0);
+        assert_eq!(integer_sqrt(1), 1);
+        assert_eq!(integer_sqrt(15), 3);
+        assert_eq!(integer_sqrt(16), 4);
+        assert_eq!(integer_sqrt(u128::MAX), u64::MAX as u128);
+    }
 }
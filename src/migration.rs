@@ -0,0 +1,260 @@
+use crate::state::{
+    store_holder, Config, Holder, HolderReward, PauseInfo, RewardIndex, State, CONFIG,
+    DEFAULT_MAX_CLAIMS, MEMBERS, PAUSE_INFO, STAKED_BALANCES, STATE, TOTAL_STAKED, TOTAL_WEIGHT,
+};
+
+use crate::claim::{next_claim_id, save_claim, Claim};
+use crate::error::ContractError;
+use crate::group::{member_weight, validate_weight_function};
+use crate::msg::MigrateMsg;
+use cosmwasm_std::{
+    CanonicalAddr, Decimal, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage,
+    Uint128, Uint64,
+};
+use cw0::Duration;
+use cw20::Expiration;
+use cw_storage_plus::{Bound, Item, Map};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Config layout of v3.0.0, the last release without cw2 version info
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigV3 {
+    pub cw20_token_addr: CanonicalAddr,
+    pub reward_denom: String,
+    pub unbonding_period: u64,
+    pub group_addr: CanonicalAddr,
+}
+pub const CONFIG_V3: Item<ConfigV3> = Item::new("\u{0}\u{6}config");
+
+/// State layout of v3.0.0, with a single reward denom
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StateV3 {
+    pub global_index: Decimal,
+    pub total_balance: Uint128,
+    pub prev_reward_balance: Uint128,
+}
+pub const STATE_V3: Item<StateV3> = Item::new("\u{0}\u{5}state");
+
+/// Holder layout of v3.0.0, with a single reward denom
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HolderV3 {
+    pub balance: Uint128,
+    pub index: Decimal,
+    pub pending_rewards: Decimal,
+}
+pub const HOLDERS_V3: Map<&[u8], HolderV3> = Map::new("holders");
+
+/// Claim layout of v3.0.0, without id and creation info
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ClaimV3 {
+    pub amount: Uint128,
+    pub release_at: Expiration,
+}
+pub const CLAIMS_V3: Map<&[u8], Vec<ClaimV3>> = Map::new("claims");
+
+/// Progress of the paged v3.0.0 migration, removed once it is complete
+/// The legacy config is overwritten by the first page, the fields still needed are kept here
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrationProgress {
+    pub reward_denom: String,
+    pub unbonding_period: u64,
+    /// Last migrated holder, holders are rewritten in place
+    pub last_holder: Option<Vec<u8>>,
+    pub holders_migrated: bool,
+}
+pub const MIGRATION_PROGRESS: Item<MigrationProgress> = Item::new("migration_progress");
+
+/// Upgrade the v3.0.0 storage layout to the current one
+/// Fields introduced since v3.0.0 are taken from the migrate msg or set to their defaults
+pub fn migrate_from_v3(deps: DepsMut, env: &Env, msg: &MigrateMsg) -> Result<(), ContractError> {
+    let owner = msg
+        .owner
+        .as_ref()
+        .ok_or(ContractError::MigrationOwnerRequired {})?;
+    let owner = deps.api.addr_validate(owner)?;
+    let guardian = msg
+        .guardian
+        .as_ref()
+        .map(|guardian| deps.api.addr_validate(guardian))
+        .transpose()?;
+    // v3.0.0 group weights are the staked balances
+    let weight_function = msg.weight_function.clone().unwrap_or_default();
+    validate_weight_function(&weight_function)?;
+
+    let legacy_config = CONFIG_V3.load(deps.storage)?;
+    let legacy_state = STATE_V3.load(deps.storage)?;
+
+    let config = Config {
+        owner: deps.api.addr_canonicalize(owner.as_str())?,
+        cw20_token_addr: legacy_config.cw20_token_addr,
+        reward_denoms: vec![legacy_config.reward_denom.clone()],
+        reward_token: None,
+        // v3.0.0 unbonding periods are counted in blocks
+        unbonding_period: Duration::Height(legacy_config.unbonding_period),
+        group_addr: Some(legacy_config.group_addr),
+        guardian: guardian
+            .map(|guardian| deps.api.addr_canonicalize(guardian.as_str()))
+            .transpose()?,
+        distribution_period: None,
+        instant_unbond_penalty: None,
+        treasury: None,
+        max_claims: DEFAULT_MAX_CLAIMS,
+        weight_function,
+    };
+    CONFIG.save(deps.storage, &config)?;
+    STATE.save(
+        deps.storage,
+        &State {
+            total_balance: legacy_state.total_balance,
+            // counted with the pages of legacy holders and claims
+            total_unbonding: Uint128::zero(),
+            reward_indices: vec![RewardIndex {
+                denom: legacy_config.reward_denom.clone(),
+                global_index: legacy_state.global_index,
+                prev_reward_balance: legacy_state.prev_reward_balance,
+                undistributed_rewards: Uint128::zero(),
+            }],
+        },
+    )?;
+    PAUSE_INFO.save(deps.storage, &PauseInfo::default())?;
+    TOTAL_STAKED.save(deps.storage, &legacy_state.total_balance, env.block.height)?;
+    // summed with the pages of legacy holders
+    TOTAL_WEIGHT.save(deps.storage, &0)?;
+
+    // holders and claims are rewritten in pages, the first one right away
+    let progress = MigrationProgress {
+        reward_denom: legacy_config.reward_denom,
+        unbonding_period: legacy_config.unbonding_period,
+        last_holder: None,
+        holders_migrated: false,
+    };
+    MIGRATION_PROGRESS.save(deps.storage, &progress)?;
+    migrate_v3_page(deps, env, msg.limit)?;
+
+    Ok(())
+}
+
+/// Rewrite the next page of v3.0.0 holders, then claims, in the current layout
+/// Returns whether the migration is complete
+pub fn migrate_v3_page(deps: DepsMut, env: &Env, limit: Option<u32>) -> StdResult<bool> {
+    let mut progress = MIGRATION_PROGRESS.load(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let mut remaining = limit.map_or(usize::MAX, |limit| limit as usize);
+
+    if !progress.holders_migrated {
+        // holders are read first, they cannot be rewritten while iterating
+        let start = progress
+            .last_holder
+            .as_ref()
+            .map(|key| Bound::exclusive(key.as_slice()));
+        let legacy_holders = HOLDERS_V3
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(remaining)
+            .collect::<StdResult<Vec<(Vec<u8>, HolderV3)>>>()?;
+        progress.holders_migrated = legacy_holders.len() < remaining;
+        remaining -= legacy_holders.len();
+
+        let mut total_weight = Uint64::from(TOTAL_WEIGHT.load(deps.storage)?);
+        for (key, legacy_holder) in legacy_holders {
+            let weight = member_weight(&config.weight_function, legacy_holder.balance)?;
+            if weight > 0 {
+                let addr = deps.api.addr_humanize(&CanonicalAddr::from(key.clone()))?;
+                MEMBERS.save(deps.storage, &addr, &weight, env.block.height)?;
+                total_weight = total_weight.checked_add(Uint64::from(weight))?;
+            }
+            STAKED_BALANCES.save(deps.storage, &key, &legacy_holder.balance, env.block.height)?;
+            store_holder(
+                deps.storage,
+                &CanonicalAddr::from(key.clone()),
+                &Holder {
+                    balance: legacy_holder.balance,
+                    rewards: vec![HolderReward {
+                        denom: progress.reward_denom.clone(),
+                        index: legacy_holder.index,
+                        pending_rewards: legacy_holder.pending_rewards,
+                    }],
+                },
+            )?;
+            progress.last_holder = Some(key);
+        }
+        TOTAL_WEIGHT.save(deps.storage, &total_weight.u64())?;
+    }
+
+    // v3.0.0 claims were created at the release height minus the unbonding period,
+    // their creation time was not recorded
+    let mut claims_migrated = false;
+    if progress.holders_migrated {
+        let unbonding_period = Duration::Height(progress.unbonding_period);
+        let legacy_claims = CLAIMS_V3
+            .range(deps.storage, None, None, Order::Ascending)
+            .take(remaining)
+            .collect::<StdResult<Vec<(Vec<u8>, Vec<ClaimV3>)>>>()?;
+        claims_migrated = legacy_claims.len() < remaining;
+
+        let mut total_unbonding = Uint128::zero();
+        for (key, legacy_claims) in legacy_claims {
+            let addr = CanonicalAddr::from(key.clone());
+            CLAIMS_V3.remove(deps.storage, &key);
+            for legacy_claim in legacy_claims {
+                let created_at_height = match legacy_claim.release_at {
+                    Expiration::AtHeight(height) => {
+                        height.saturating_sub(progress.unbonding_period)
+                    }
+                    _ => env.block.height,
+                };
+                let claim = Claim {
+                    id: next_claim_id(deps.storage)?,
+                    amount: legacy_claim.amount,
+                    release_at: legacy_claim.release_at,
+                    created_at_height,
+                    created_at_time: None,
+                    unbonding_period,
+                };
+                total_unbonding += claim.amount;
+                save_claim(deps.storage, &addr, &claim)?;
+            }
+        }
+        STATE.update(deps.storage, |mut state| -> StdResult<_> {
+            state.total_unbonding += total_unbonding;
+            Ok(state)
+        })?;
+    }
+
+    if claims_migrated {
+        MIGRATION_PROGRESS.remove(deps.storage);
+    } else {
+        MIGRATION_PROGRESS.save(deps.storage, &progress)?;
+    }
+    Ok(claims_migrated)
+}
+
+/// Continue the v3.0.0 migration with the next page of holders and claims
+/// Only the owner is allowed to execute
+pub fn handle_continue_migration(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if MIGRATION_PROGRESS.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoPendingMigration {});
+    }
+
+    let complete = migrate_v3_page(deps, &env, limit)?;
+    Ok(Response::new()
+        .add_attribute("action", "continue_migration")
+        .add_attribute("complete", complete.to_string()))
+}
+
+/// Return an error while the v3.0.0 holders and claims are not all migrated
+pub fn assert_migrated(storage: &dyn Storage) -> Result<(), ContractError> {
+    if MIGRATION_PROGRESS.may_load(storage)?.is_some() {
+        return Err(ContractError::MigrationPending {});
+    }
+    Ok(())
+}
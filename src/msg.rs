@@ -1,15 +1,31 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Decimal, Uint128};
-use cw20::Cw20ReceiveMsg;
+use crate::state::{HolderReward, RewardIndex, WeightFunction};
+use cosmwasm_std::{Addr, Coin, Decimal, Uint128};
+use cw0::Duration;
+use cw20::{Cw20ReceiveMsg, Expiration};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     pub cw20_token_addr: Addr,
-    pub reward_denom: String,
-    pub unbonding_period: u64,
-    pub group_addr: Addr,
+    pub reward_denoms: Vec<String>,
+    pub reward_token: Option<Addr>,
+    /// Unbonding period in blocks or seconds
+    pub unbonding_period: Duration,
+    /// cw4 group mirroring the member weights, no group messages are sent if not set
+    pub group_addr: Option<Addr>,
+    pub guardian: Option<Addr>,
+    /// Seconds over which deposited rewards are released, rewards are indexed at once if not set
+    pub distribution_period: Option<u64>,
+    /// Share of the stake kept on instant unbond, instant unbond is disabled if not set
+    pub instant_unbond_penalty: Option<Decimal>,
+    /// Receives the instant unbond penalties, they are redistributed to stakers if not set
+    pub treasury: Option<Addr>,
+    /// Maximum number of outstanding claims per holder, 50 if not set
+    pub max_claims: Option<u32>,
+    /// Conversion of staked balances into group weights, one weight per staked unit if not set
+    pub weight_function: Option<WeightFunction>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -22,6 +38,52 @@ pub enum ExecuteMsg {
     /// Update the global index
     UpdateGlobalIndex {},
 
+    /// Update the config, only the owner can execute
+    UpdateConfig {
+        unbonding_period: Option<Duration>,
+        reward_denoms: Option<Vec<String>>,
+        reward_token: Option<String>,
+        guardian: Option<String>,
+        /// Zero switches back to indexing rewards at once
+        distribution_period: Option<u64>,
+        instant_unbond_penalty: Option<Decimal>,
+        /// Unset the penalty, disabling instant unbond
+        disable_instant_unbond: Option<bool>,
+        treasury: Option<String>,
+        /// Unset the treasury, the penalties are redistributed to stakers again
+        remove_treasury: Option<bool>,
+        max_claims: Option<u32>,
+    },
+
+    /// Propose a new owner, the proposal must be accepted by the new owner before it expires
+    ProposeNewOwner { owner: String, expires: Expiration },
+
+    /// Accept a pending ownership proposal, only the proposed owner can execute
+    AcceptOwnership {},
+
+    /// Cancel a pending ownership proposal
+    CancelOwnershipProposal {},
+
+    /// Pause the given operations, the owner or the guardian can execute
+    Pause { operations: Vec<Operation> },
+
+    /// Unpause the given operations, only the owner can execute
+    Unpause { operations: Vec<Operation> },
+
+    /// Attach a cw4 group, or detach it without an address, only the owner can execute
+    /// Sync an attached group to set the weights of the existing holders
+    UpdateGroup { group_addr: Option<String> },
+
+    /// Set the cw4 group weights of a page of holders from their staked balance
+    /// Holders without weight are removed, only the owner can execute
+    SyncGroup {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    /// Migrate the next page of v3.0.0 holders and claims, only the owner can execute
+    ContinueMigration { limit: Option<u32> },
+
     ////////////////////
     /// Staking operations
     ///////////////////
@@ -31,28 +93,61 @@ pub enum ExecuteMsg {
     /// Set current reward index to global index
     UnbondStake { amount: Uint128 },
 
+    /// Unbound user staking balance without waiting for the unbonding period
+    /// The instant unbond penalty is kept from the amount
+    InstantUnbond { amount: Uint128 },
+
+    /// Bond back stake from the newest claims still in the unbonding period
+    /// All of them are rebonded without an amount
+    Rebond { amount: Option<Uint128> },
+
+    /// Move bonded stake to the recipient without unbonding
+    /// Rewards accrued until now stay with the sender
+    TransferStake { recipient: String, amount: Uint128 },
+
     /// Unbound user staking balance
-    /// Withdraws released stake
-    WithdrawStake { cap: Option<Uint128> },
+    /// Withdraws released stake, up to the cap when set
+    /// The stake is sent to the recipient, or to the holder without one
+    WithdrawStake {
+        cap: Option<Uint128>,
+        recipient: Option<String>,
+    },
 
     ////////////////////
     /// User's operations
     ///////////////////
 
-    /// return the accrued reward in usdt to the user.
+    /// return the accrued rewards in every reward denom to the user.
     ClaimRewards { recipient: Option<String> },
 
+    /// Bond the accrued rewards, only when rewards are paid in the staking token
+    CompoundRewards {},
+
     /// This accepts a properly-encoded ReceiveMsg from a cw20 contract
     Receive(Cw20ReceiveMsg),
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Bond,
+    Unbond,
+    Withdraw,
+    Claim,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ReceiveMsg {
     /// Bond stake user staking balance
     /// Withdraw rewards to pending rewards
     /// Set current reward index to global index
-    BondStake {},
+    /// The recipient is credited when set, the cw20 sender otherwise
+    BondStake { recipient: Option<String> },
+
+    /// Deposit rewards paid in the cw20 reward token
+    /// Increase the reward token global index
+    DistributeRewards {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -72,34 +167,102 @@ pub enum QueryMsg {
     },
     Claims {
         address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Claims of every holder, starting after the given address and claim id
+    AllClaims {
+        start_after: Option<(String, u64)>,
+        limit: Option<u32>,
+    },
+    /// Pending ownership proposal, null if there is none
+    OwnershipProposal {},
+    PauseStatus {},
+    /// Staked balance of the address at the start of the block height, the current height if not set
+    StakedBalanceAtHeight {
+        address: String,
+        height: Option<u64>,
+    },
+    /// Total staked at the start of the block height, the current height if not set
+    TotalStakedAtHeight {
+        height: Option<u64>,
+    },
+
+    ////////////////////
+    /// cw4 group queries, the staked balances are the member weights
+    ///////////////////
+
+    /// Return cw4 MemberResponse
+    Member {
+        addr: String,
+        at_height: Option<u64>,
+    },
+    /// Return cw4 MemberListResponse
+    ListMembers {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Return cw4 TotalWeightResponse
+    TotalWeight {},
+    /// Return cw4 AdminResponse, the contract owner
+    Admin {},
+    /// Return cw4 HooksResponse, always empty
+    Hooks {},
+    /// Holders of the page whose cw4 group weight differs from their staked balance weight
+    GroupDivergence {
+        start_after: Option<String>,
+        limit: Option<u32>,
     },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ConfigResponse {
+    pub owner: String,
+    pub guardian: Option<String>,
     pub cw20_token_addr: String,
-    pub reward_denom: String,
-    pub unbonding_period: u64,
+    pub reward_denoms: Vec<String>,
+    pub reward_token: Option<String>,
+    pub unbonding_period: Duration,
+    pub distribution_period: Option<u64>,
+    pub instant_unbond_penalty: Option<Decimal>,
+    pub treasury: Option<String>,
+    pub max_claims: u32,
+    pub weight_function: WeightFunction,
+    pub group_addr: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnershipProposalResponse {
+    pub owner: String,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PauseStatusResponse {
+    pub bond: bool,
+    pub unbond: bool,
+    pub withdraw: bool,
+    pub claim: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct StateResponse {
-    pub global_index: Decimal,
     pub total_balance: Uint128,
-    pub prev_reward_balance: Uint128,
+    /// Stake unbonded and not withdrawn yet
+    pub total_unbonding: Uint128,
+    pub reward_indices: Vec<RewardIndex>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct AccruedRewardsResponse {
-    pub rewards: Uint128,
+    pub rewards: Vec<Coin>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct HolderResponse {
     pub address: String,
     pub balance: Uint128,
-    pub index: Decimal,
-    pub pending_rewards: Decimal,
+    pub rewards: Vec<HolderReward>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -108,4 +271,42 @@ pub struct HoldersResponse {
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct MigrateMsg {}
+pub struct StakedBalanceAtHeightResponse {
+    pub balance: Uint128,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TotalStakedAtHeightResponse {
+    pub total: Uint128,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WeightDivergence {
+    pub address: String,
+    /// Weight of the staked balance, None without weight
+    pub weight: Option<u64>,
+    /// Weight in the cw4 group, None if not a member
+    pub group_weight: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GroupDivergenceResponse {
+    pub divergences: Vec<WeightDivergence>,
+    /// Last holder of the page, to start the next page after
+    pub last_holder: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// Owner to set when migrating from v3.0.0, which had no owner
+    pub owner: Option<String>,
+    /// Guardian to set when migrating from v3.0.0
+    pub guardian: Option<String>,
+    /// Weight function to set when migrating from v3.0.0, defaults to the staked balance
+    pub weight_function: Option<WeightFunction>,
+    /// Holders and claims to migrate from v3.0.0 right away, all of them if not set
+    /// The rest is migrated with ContinueMigration, the contract is locked until then
+    pub limit: Option<u32>,
+}
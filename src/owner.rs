@@ -0,0 +1,290 @@
+use crate::state::{
+    Config, OwnershipProposal, RewardIndex, State, CONFIG, OWNERSHIP_PROPOSAL, STATE,
+};
+
+use crate::error::ContractError;
+use crate::global::advance_reward_streams;
+use crate::msg::OwnershipProposalResponse;
+use cosmwasm_std::{Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Uint128};
+use cw0::Duration;
+use cw20::Expiration;
+
+/// Update the config
+/// Only the owner is allowed to execute
+#[allow(clippy::too_many_arguments)]
+pub fn handle_update_config(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    unbonding_period: Option<Duration>,
+    reward_denoms: Option<Vec<String>>,
+    reward_token: Option<String>,
+    guardian: Option<String>,
+    distribution_period: Option<u64>,
+    instant_unbond_penalty: Option<Decimal>,
+    disable_instant_unbond: Option<bool>,
+    treasury: Option<String>,
+    remove_treasury: Option<bool>,
+    max_claims: Option<u32>,
+) -> Result<Response, ContractError> {
+    let mut config: Config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut res = Response::new().add_attribute("action", "update_config");
+
+    if let Some(unbonding_period) = unbonding_period {
+        config.unbonding_period = unbonding_period;
+        res = res.add_attribute("unbonding_period", unbonding_period.to_string());
+    }
+
+    if let Some(reward_denoms) = reward_denoms {
+        validate_reward_denoms(&reward_denoms)?;
+        // removing a denom would lock the rewards already accrued by holders
+        if let Some(denom) = config
+            .reward_denoms
+            .iter()
+            .find(|denom| !reward_denoms.contains(denom))
+        {
+            return Err(ContractError::RewardDenomRemoved {
+                denom: denom.clone(),
+            });
+        }
+
+        let mut state: State = STATE.load(deps.storage)?;
+        for denom in reward_denoms.iter() {
+            if !config.reward_denoms.contains(denom) {
+                state.reward_indices.push(RewardIndex {
+                    denom: denom.clone(),
+                    global_index: Decimal::zero(),
+                    prev_reward_balance: Uint128::zero(),
+                    undistributed_rewards: Uint128::zero(),
+                });
+            }
+        }
+        STATE.save(deps.storage, &state)?;
+
+        res = res.add_attribute("reward_denoms", reward_denoms.join(","));
+        config.reward_denoms = reward_denoms;
+    }
+
+    if let Some(reward_token) = reward_token {
+        // replacing the token would lock the rewards already accrued by holders
+        if config.reward_token.is_some() {
+            return Err(ContractError::RewardTokenAlreadySet {});
+        }
+        let reward_token = deps.api.addr_validate(&reward_token)?;
+
+        let mut state: State = STATE.load(deps.storage)?;
+        state.reward_indices.push(RewardIndex {
+            denom: reward_token.to_string(),
+            global_index: Decimal::zero(),
+            prev_reward_balance: Uint128::zero(),
+            undistributed_rewards: Uint128::zero(),
+        });
+        STATE.save(deps.storage, &state)?;
+
+        config.reward_token = Some(deps.api.addr_canonicalize(reward_token.as_str())?);
+        res = res.add_attribute("reward_token", reward_token);
+    }
+
+    if let Some(guardian) = guardian {
+        let guardian = deps.api.addr_validate(&guardian)?;
+        config.guardian = Some(deps.api.addr_canonicalize(guardian.as_str())?);
+        res = res.add_attribute("guardian", guardian);
+    }
+
+    if let Some(distribution_period) = distribution_period {
+        // release the rewards streamed so far at the previous pace
+        let mut state: State = STATE.load(deps.storage)?;
+        advance_reward_streams(deps.storage, &mut state, env.block.time.seconds())?;
+        STATE.save(deps.storage, &state)?;
+
+        config.distribution_period = Some(distribution_period).filter(|period| *period > 0);
+        res = res.add_attribute("distribution_period", distribution_period.to_string());
+    }
+
+    if disable_instant_unbond == Some(true) {
+        if instant_unbond_penalty.is_some() {
+            return Err(ContractError::ConflictingUpdate {
+                field: "instant_unbond_penalty".to_string(),
+            });
+        }
+        config.instant_unbond_penalty = None;
+        res = res.add_attribute("instant_unbond_penalty", "none");
+    }
+
+    if let Some(penalty) = instant_unbond_penalty {
+        validate_penalty(penalty)?;
+        config.instant_unbond_penalty = Some(penalty);
+        res = res.add_attribute("instant_unbond_penalty", penalty.to_string());
+    }
+
+    if remove_treasury == Some(true) {
+        if treasury.is_some() {
+            return Err(ContractError::ConflictingUpdate {
+                field: "treasury".to_string(),
+            });
+        }
+        config.treasury = None;
+        res = res.add_attribute("treasury", "none");
+    }
+
+    if let Some(treasury) = treasury {
+        let treasury = deps.api.addr_validate(&treasury)?;
+        config.treasury = Some(deps.api.addr_canonicalize(treasury.as_str())?);
+        res = res.add_attribute("treasury", treasury);
+    }
+
+    if let Some(max_claims) = max_claims {
+        validate_max_claims(max_claims)?;
+        config.max_claims = max_claims;
+        res = res.add_attribute("max_claims", max_claims.to_string());
+    }
+
+    validate_penalty_recipient(&config)?;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(res)
+}
+
+/// Propose a new owner, replacing any pending proposal
+/// Only the owner is allowed to execute
+pub fn handle_propose_new_owner(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    expires: Expiration,
+) -> Result<Response, ContractError> {
+    let config: Config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let new_owner = deps.api.addr_validate(&owner)?;
+    let new_owner_raw = deps.api.addr_canonicalize(new_owner.as_str())?;
+    if new_owner_raw == config.owner {
+        return Err(ContractError::SameOwner {});
+    }
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::InvalidExpiration {});
+    }
+
+    OWNERSHIP_PROPOSAL.save(
+        deps.storage,
+        &OwnershipProposal {
+            owner: new_owner_raw,
+            expires,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("action", "propose_new_owner")
+        .add_attribute("new_owner", new_owner)
+        .add_attribute("expires", expires.to_string()))
+}
+
+/// Accept the pending ownership proposal
+/// Only the proposed owner is allowed to execute, before the proposal expires
+pub fn handle_accept_ownership(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let proposal = OWNERSHIP_PROPOSAL
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoOwnershipProposal {})?;
+
+    if deps.api.addr_canonicalize(info.sender.as_str())? != proposal.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if proposal.expires.is_expired(&env.block) {
+        return Err(ContractError::OwnershipProposalExpired {});
+    }
+
+    let mut config: Config = CONFIG.load(deps.storage)?;
+    let previous_owner = deps.api.addr_humanize(&config.owner)?;
+    config.owner = proposal.owner;
+    CONFIG.save(deps.storage, &config)?;
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
+
+    Ok(Response::new()
+        .add_attribute("action", "accept_ownership")
+        .add_attribute("previous_owner", previous_owner)
+        .add_attribute("new_owner", info.sender))
+}
+
+/// Cancel the pending ownership proposal
+/// Only the owner is allowed to execute
+pub fn handle_cancel_ownership_proposal(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let config: Config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if OWNERSHIP_PROPOSAL.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoOwnershipProposal {});
+    }
+
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
+
+    Ok(Response::new().add_attribute("action", "cancel_ownership_proposal"))
+}
+
+/// Pending ownership proposal, none once accepted or cancelled
+pub fn query_ownership_proposal(deps: Deps) -> StdResult<Option<OwnershipProposalResponse>> {
+    OWNERSHIP_PROPOSAL
+        .may_load(deps.storage)?
+        .map(|proposal| {
+            Ok(OwnershipProposalResponse {
+                owner: deps.api.addr_humanize(&proposal.owner)?.to_string(),
+                expires: proposal.expires,
+            })
+        })
+        .transpose()
+}
+
+/// Return an error if a reward denom is listed more than once
+pub fn validate_reward_denoms(reward_denoms: &[String]) -> Result<(), ContractError> {
+    for (i, denom) in reward_denoms.iter().enumerate() {
+        if reward_denoms[..i].contains(denom) {
+            return Err(ContractError::DuplicateRewardDenom {
+                denom: denom.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Return an error if the instant unbond penalty would keep the whole stake
+pub fn validate_penalty(penalty: Decimal) -> Result<(), ContractError> {
+    if penalty >= Decimal::one() {
+        return Err(ContractError::InvalidPenalty {});
+    }
+    Ok(())
+}
+
+/// Return an error if an instant unbond penalty is set but could not be collected,
+/// it goes to the treasury or to the stakers when rewards are paid in the staking token
+pub fn validate_penalty_recipient(config: &Config) -> Result<(), ContractError> {
+    let penalty = config.instant_unbond_penalty.unwrap_or_default();
+    if !penalty.is_zero()
+        && config.treasury.is_none()
+        && config.reward_token.as_ref() != Some(&config.cw20_token_addr)
+    {
+        return Err(ContractError::NoPenaltyRecipient {});
+    }
+    Ok(())
+}
+
+/// Return an error if no claim could ever be created
+pub fn validate_max_claims(max_claims: u32) -> Result<(), ContractError> {
+    if max_claims == 0 {
+        return Err(ContractError::InvalidMaxClaims {});
+    }
+    Ok(())
+}
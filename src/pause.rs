@@ -0,0 +1,105 @@
+use crate::state::{Config, PauseInfo, CONFIG, PAUSE_INFO};
+
+use crate::error::ContractError;
+use crate::msg::{Operation, PauseStatusResponse};
+use cosmwasm_std::{Deps, DepsMut, MessageInfo, Response, StdResult, Storage};
+
+/// Pause the given operations
+/// The owner and the guardian are allowed to execute
+pub fn handle_pause(
+    deps: DepsMut,
+    info: MessageInfo,
+    operations: Vec<Operation>,
+) -> Result<Response, ContractError> {
+    let config: Config = CONFIG.load(deps.storage)?;
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if sender_raw != config.owner && Some(sender_raw) != config.guardian {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let pause_info = set_paused(deps.storage, &operations, true)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "pause")
+        .add_attribute("paused", paused_operations(&pause_info)))
+}
+
+/// Unpause the given operations
+/// Only the owner is allowed to execute
+pub fn handle_unpause(
+    deps: DepsMut,
+    info: MessageInfo,
+    operations: Vec<Operation>,
+) -> Result<Response, ContractError> {
+    let config: Config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let pause_info = set_paused(deps.storage, &operations, false)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "unpause")
+        .add_attribute("paused", paused_operations(&pause_info)))
+}
+
+/// Return an error if the operation is currently paused
+pub fn assert_not_paused(storage: &dyn Storage, operation: Operation) -> Result<(), ContractError> {
+    let pause_info = PAUSE_INFO.may_load(storage)?.unwrap_or_default();
+    let paused = match operation {
+        Operation::Bond => pause_info.bond,
+        Operation::Unbond => pause_info.unbond,
+        Operation::Withdraw => pause_info.withdraw,
+        Operation::Claim => pause_info.claim,
+    };
+    if paused {
+        return Err(ContractError::Paused {});
+    }
+    Ok(())
+}
+
+pub fn query_pause_status(deps: Deps) -> StdResult<PauseStatusResponse> {
+    let pause_info = PAUSE_INFO.may_load(deps.storage)?.unwrap_or_default();
+    Ok(PauseStatusResponse {
+        bond: pause_info.bond,
+        unbond: pause_info.unbond,
+        withdraw: pause_info.withdraw,
+        claim: pause_info.claim,
+    })
+}
+
+fn set_paused(
+    storage: &mut dyn Storage,
+    operations: &[Operation],
+    paused: bool,
+) -> StdResult<PauseInfo> {
+    let mut pause_info = PAUSE_INFO.may_load(storage)?.unwrap_or_default();
+    for operation in operations {
+        match operation {
+            Operation::Bond => pause_info.bond = paused,
+            Operation::Unbond => pause_info.unbond = paused,
+            Operation::Withdraw => pause_info.withdraw = paused,
+            Operation::Claim => pause_info.claim = paused,
+        }
+    }
+    PAUSE_INFO.save(storage, &pause_info)?;
+    Ok(pause_info)
+}
+
+// list the paused operations for the event attributes
+fn paused_operations(pause_info: &PauseInfo) -> String {
+    let mut paused = vec![];
+    if pause_info.bond {
+        paused.push("bond");
+    }
+    if pause_info.unbond {
+        paused.push("unbond");
+    }
+    if pause_info.withdraw {
+        paused.push("withdraw");
+    }
+    if pause_info.claim {
+        paused.push("claim");
+    }
+    paused.join(",")
+}
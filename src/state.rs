@@ -1,34 +1,139 @@
 use crate::msg::HolderResponse;
 use cosmwasm_std::{Addr, Api, CanonicalAddr, Decimal, Deps, Order, StdResult, Storage, Uint128};
-use cw_storage_plus::{Bound, Item, Map};
+use cw0::Duration;
+use cw20::Expiration;
+use cw_storage_plus::{Bound, Item, Map, SnapshotItem, SnapshotMap, Strategy};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
+    pub owner: CanonicalAddr,
     pub cw20_token_addr: CanonicalAddr,
-    pub reward_denom: String,
-    pub unbonding_period: u64,
-    pub group_addr: CanonicalAddr,
+    pub reward_denoms: Vec<String>,
+    pub reward_token: Option<CanonicalAddr>,
+    pub unbonding_period: Duration,
+    pub group_addr: Option<CanonicalAddr>,
+    pub guardian: Option<CanonicalAddr>,
+    pub distribution_period: Option<u64>,
+    pub instant_unbond_penalty: Option<Decimal>,
+    pub treasury: Option<CanonicalAddr>,
+    /// Maximum number of outstanding claims per holder
+    pub max_claims: u32,
+    pub weight_function: WeightFunction,
 }
 pub const CONFIG: Item<Config> = Item::new("\u{0}\u{6}config");
 
+pub const DEFAULT_MAX_CLAIMS: u32 = 50;
+
+/// Conversion of staked balances into cw4 member weights
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct State {
+pub struct WeightFunction {
+    /// Staked amount per unit of weight, e.g. 1000000 for a token with 6 decimals
+    pub scaling_factor: Uint128,
+    /// Maximum weight of a single member
+    pub max_weight: Option<u64>,
+    /// The weight is the square root of the scaled balance, for quadratic voting
+    pub square_root: bool,
+}
+
+impl Default for WeightFunction {
+    /// One unit of weight per staked token unit
+    fn default() -> Self {
+        WeightFunction {
+            scaling_factor: Uint128::from(1u128),
+            max_weight: None,
+            square_root: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnershipProposal {
+    pub owner: CanonicalAddr,
+    pub expires: Expiration,
+}
+pub const OWNERSHIP_PROPOSAL: Item<OwnershipProposal> = Item::new("ownership_proposal");
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PauseInfo {
+    pub bond: bool,
+    pub unbond: bool,
+    pub withdraw: bool,
+    pub claim: bool,
+}
+pub const PAUSE_INFO: Item<PauseInfo> = Item::new("pause_info");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardIndex {
+    /// Native denom, or the contract address of the cw20 reward token
+    pub denom: String,
     pub global_index: Decimal,
-    pub total_balance: Uint128,
     pub prev_reward_balance: Uint128,
+    /// Rewards received while nothing was bonded, indexed with the next rewards
+    pub undistributed_rewards: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct State {
+    pub total_balance: Uint128,
+    /// Stake unbonded and not withdrawn yet, the cw20 balance is total_balance + total_unbonding
+    pub total_unbonding: Uint128,
+    pub reward_indices: Vec<RewardIndex>,
 }
 pub const STATE: Item<State> = Item::new("\u{0}\u{5}state");
 
+/// Total staked balance at every height it changed, State.total_balance is the current one
+pub const TOTAL_STAKED: SnapshotItem<Uint128> = SnapshotItem::new(
+    "total_staked",
+    "total_staked__checkpoints",
+    "total_staked__changelog",
+    Strategy::EveryBlock,
+);
+
+/// cw4 member weights, stored under the cw4 raw query keys, holders without weight are removed
+pub const MEMBERS: SnapshotMap<&Addr, u64> = SnapshotMap::new(
+    cw4::MEMBERS_KEY,
+    cw4::MEMBERS_CHECKPOINTS,
+    cw4::MEMBERS_CHANGELOG,
+    Strategy::EveryBlock,
+);
+
+/// Sum of the cw4 member weights, stored under the cw4 raw query key
+pub const TOTAL_WEIGHT: Item<u64> = Item::new(cw4::TOTAL_KEY);
+
+/// Rewards of a denom being released linearly until period_finish
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct RewardStream {
+    /// Rewards released per second
+    pub reward_rate: Decimal,
+    pub period_finish: u64,
+    pub last_update_time: u64,
+}
+pub const REWARD_STREAMS: Map<&str, RewardStream> = Map::new("reward_streams");
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct Holder {
-    pub balance: Uint128,
+pub struct HolderReward {
+    pub denom: String,
     pub index: Decimal,
     pub pending_rewards: Decimal,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Holder {
+    pub balance: Uint128,
+    pub rewards: Vec<HolderReward>,
+}
+
 pub const PREFIXED_HOLDERS: Map<&[u8], Holder> = Map::new("holders");
+
+/// Holder staked balances at every height they changed, Holder.balance is the current one
+pub const STAKED_BALANCES: SnapshotMap<&[u8], Uint128> = SnapshotMap::new(
+    "staked_balances",
+    "staked_balances__checkpoints",
+    "staked_balances__changelog",
+    Strategy::EveryBlock,
+);
 // This is similar to HashMap<holder's address, Hodler>
 pub fn store_holder(
     storage: &mut dyn Storage,
@@ -45,15 +150,24 @@ pub fn read_holder(storage: &dyn Storage, holder_address: &CanonicalAddr) -> Std
         Some(holder) => Ok(holder),
         None => Ok(Holder {
             balance: Uint128::zero(),
-            index: Decimal::zero(),
-            pending_rewards: Decimal::zero(),
+            rewards: vec![],
         }),
     }
 }
 
+/// Return the key of the cw20 reward token in the reward indices, if any
+pub fn reward_token_denom(api: &dyn Api, config: &Config) -> StdResult<Option<String>> {
+    config
+        .reward_token
+        .as_ref()
+        .map(|token| Ok(api.addr_humanize(token)?.to_string()))
+        .transpose()
+}
+
 // settings for pagination
-const MAX_LIMIT: u32 = 30;
-const DEFAULT_LIMIT: u32 = 10;
+pub(crate) const MAX_LIMIT: u32 = 30;
+pub(crate) const DEFAULT_LIMIT: u32 = 10;
+
 pub fn read_holders(
     deps: Deps,
     start_after: Option<Addr>,
@@ -74,14 +188,16 @@ pub fn read_holders(
             Ok(HolderResponse {
                 address,
                 balance: v.balance,
-                index: v.index,
-                pending_rewards: v.pending_rewards,
+                rewards: v.rewards,
             })
         })
         .collect()
 }
 
-fn calc_range_start(api: &dyn Api, start_after: Option<Addr>) -> StdResult<Option<Vec<u8>>> {
+pub(crate) fn calc_range_start(
+    api: &dyn Api,
+    start_after: Option<Addr>,
+) -> StdResult<Option<Vec<u8>>> {
     match start_after {
         Some(human) => {
             let mut v: Vec<u8> = api.addr_canonicalize(human.as_ref())?.0.into();
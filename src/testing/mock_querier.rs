@@ -1,14 +1,17 @@
 use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    from_slice, to_binary, Coin, ContractResult, Decimal, OwnedDeps, Querier, QuerierResult,
-    QueryRequest, SystemError, SystemResult, Uint128,
+    from_binary, from_slice, to_binary, Coin, ContractResult, Decimal, OwnedDeps, Querier,
+    QuerierResult, QueryRequest, SystemError, SystemResult, Uint128, WasmQuery,
 };
+use cw4::{Cw4QueryMsg, MemberResponse};
+use std::collections::HashMap;
 use terra_cosmwasm::{TaxCapResponse, TaxRateResponse, TerraQuery, TerraQueryWrapper};
 
 pub const MOCK_HUB_CONTRACT_ADDR: &str = "hub";
 pub const MOCK_CW20_CONTRACT_ADDR: &str = "lottery";
 //pub const MOCK_REWARD_CONTRACT_ADDR: &str = "reward";
 pub const MOCK_TOKEN_CONTRACT_ADDR: &str = "token";
+pub const MOCK_GROUP_CONTRACT_ADDR: &str = "group";
 
 pub fn mock_dependencies(
     contract_balance: &[Coin],
@@ -24,6 +27,7 @@ pub fn mock_dependencies(
 
 pub struct WasmMockQuerier {
     base: MockQuerier<TerraQueryWrapper>,
+    group_members: HashMap<String, u64>,
 }
 
 impl Querier for WasmMockQuerier {
@@ -61,6 +65,19 @@ impl WasmMockQuerier {
                 }
                 _ => panic!("DO NOT ENTER HERE"),
             },
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg })
+                if contract_addr == MOCK_GROUP_CONTRACT_ADDR =>
+            {
+                match from_binary(msg).unwrap() {
+                    Cw4QueryMsg::Member { addr, .. } => {
+                        let res = MemberResponse {
+                            weight: self.group_members.get(&addr).copied(),
+                        };
+                        SystemResult::Ok(ContractResult::from(to_binary(&res)))
+                    }
+                    _ => panic!("DO NOT ENTER HERE"),
+                }
+            }
             _ => self.base.handle_query(request),
         }
     }
@@ -68,6 +85,23 @@ impl WasmMockQuerier {
 
 impl WasmMockQuerier {
     pub fn new(base: MockQuerier<TerraQueryWrapper>) -> Self {
-        WasmMockQuerier { base }
+        WasmMockQuerier {
+            base,
+            group_members: HashMap::new(),
+        }
+    }
+
+    // set the contract balance, e.g. after paying rewards
+    pub fn update_balance(&mut self, contract_balance: &[Coin]) {
+        self.base
+            .update_balance(MOCK_CONTRACT_ADDR, contract_balance.to_vec());
+    }
+
+    // set the member weights answered by the cw4 group
+    pub fn update_group_members(&mut self, members: &[(&str, u64)]) {
+        self.group_members = members
+            .iter()
+            .map(|(addr, weight)| (addr.to_string(), *weight))
+            .collect();
     }
 }
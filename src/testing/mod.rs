@@ -1,2 +1,3 @@
 mod mock_querier;
+#[allow(clippy::doc_overindented_list_items, clippy::module_inception)]
 mod tests;
@@ -19,25 +19,37 @@
 
 #[cfg(test)]
 mod tests {
-    use cosmwasm_std::testing::{mock_env, mock_info};
+    use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockStorage};
     use cosmwasm_std::{
-        from_binary, to_binary, Addr, Api, BankMsg, Coin, CosmosMsg, Decimal, MessageInfo,
-        StdError, SubMsg, Uint128, WasmMsg,
+        attr, from_binary, from_slice, to_binary, Addr, Api, BankMsg, Coin, CosmosMsg, Decimal,
+        MessageInfo, OwnedDeps, StdError, Storage, SubMsg, Uint128, WasmMsg,
     };
 
-    use crate::contract::{execute, instantiate, query};
+    use crate::claim::{AllClaimsResponse, Claim, ClaimsResponse};
+    use crate::contract::{execute, instantiate, migrate, query};
+    use crate::error::ContractError;
     use crate::math::{decimal_multiplication_in_256, decimal_subtraction_in_256};
+    use crate::migration::{
+        ClaimV3, ConfigV3, HolderV3, StateV3, CLAIMS_V3, CONFIG_V3, HOLDERS_V3, STATE_V3,
+    };
     use crate::msg::{
-        ConfigResponse, ExecuteMsg, HolderResponse, HoldersResponse, InstantiateMsg, QueryMsg,
-        ReceiveMsg, StateResponse,
+        AccruedRewardsResponse, ConfigResponse, ExecuteMsg, GroupDivergenceResponse,
+        HolderResponse, HoldersResponse, InstantiateMsg, MigrateMsg, Operation,
+        OwnershipProposalResponse, PauseStatusResponse, QueryMsg, ReceiveMsg,
+        StakedBalanceAtHeightResponse, StateResponse, TotalStakedAtHeightResponse,
+        WeightDivergence,
+    };
+    use crate::state::{
+        store_holder, Holder, HolderReward, RewardIndex, State, WeightFunction, STATE,
     };
-    use crate::state::{store_holder, Holder, State, STATE};
     use crate::testing::mock_querier::{
-        mock_dependencies, MOCK_CW20_CONTRACT_ADDR, MOCK_HUB_CONTRACT_ADDR,
-        MOCK_TOKEN_CONTRACT_ADDR,
+        mock_dependencies, WasmMockQuerier, MOCK_CW20_CONTRACT_ADDR, MOCK_GROUP_CONTRACT_ADDR,
+        MOCK_HUB_CONTRACT_ADDR, MOCK_TOKEN_CONTRACT_ADDR,
     };
 
-    use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+    use cw0::Duration;
+    use cw2::{get_contract_version, set_contract_version};
+    use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Expiration};
     use std::str::FromStr;
 
     const DEFAULT_REWARD_DENOM: &str = "uusd";
@@ -47,14 +59,21 @@ mod tests {
     fn default_init() -> InstantiateMsg {
         InstantiateMsg {
             cw20_token_addr: Addr::unchecked(MOCK_CW20_CONTRACT_ADDR),
-            reward_denom: DEFAULT_REWARD_DENOM.to_string(),
-            unbonding_period: 1000,
-            group_addr: Addr::unchecked("group"),
+            reward_denoms: vec![DEFAULT_REWARD_DENOM.to_string()],
+            reward_token: None,
+            unbonding_period: Duration::Height(1000),
+            group_addr: Some(Addr::unchecked(MOCK_GROUP_CONTRACT_ADDR)),
+            guardian: Some(Addr::unchecked("guardian")),
+            distribution_period: None,
+            instant_unbond_penalty: None,
+            treasury: None,
+            max_claims: None,
+            weight_function: None,
         }
     }
 
     fn receive_stake_msg(sender: &str, amount: u128) -> ExecuteMsg {
-        let bond_msg = ReceiveMsg::BondStake {};
+        let bond_msg = ReceiveMsg::BondStake { recipient: None };
         let cw20_receive_msg = Cw20ReceiveMsg {
             sender: sender.to_string(),
             amount: Uint128::from(amount),
@@ -63,13 +82,27 @@ mod tests {
         ExecuteMsg::Receive(cw20_receive_msg)
     }
 
+    // the contract balance decreases by the rewards paid on claim
+    fn pay_rewards(deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier>) {
+        let state = STATE.load(&deps.storage).unwrap();
+        let balance: Vec<Coin> = state
+            .reward_indices
+            .iter()
+            .map(|reward_index| Coin {
+                denom: reward_index.denom.clone(),
+                amount: reward_index.prev_reward_balance,
+            })
+            .collect();
+        deps.querier.update_balance(&balance);
+    }
+
     #[test]
     fn proper_init() {
         let mut deps = mock_dependencies(&[]);
         let init_msg = default_init();
         let env = mock_env();
         let info = MessageInfo {
-            sender: Addr::unchecked("ok"),
+            sender: Addr::unchecked("owner"),
             funds: vec![],
         };
         let res = instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
@@ -80,9 +113,18 @@ mod tests {
         assert_eq!(
             config_response,
             ConfigResponse {
+                owner: "owner".to_string(),
+                guardian: Some("guardian".to_string()),
                 cw20_token_addr: MOCK_CW20_CONTRACT_ADDR.to_string(),
-                reward_denom: DEFAULT_REWARD_DENOM.to_string(),
-                unbonding_period: 1000
+                reward_denoms: vec![DEFAULT_REWARD_DENOM.to_string()],
+                reward_token: None,
+                unbonding_period: Duration::Height(1000),
+                distribution_period: None,
+                instant_unbond_penalty: None,
+                treasury: None,
+                max_claims: 50,
+                weight_function: WeightFunction::default(),
+                group_addr: Some(MOCK_GROUP_CONTRACT_ADDR.to_string()),
             }
         );
 
@@ -91,152 +133,413 @@ mod tests {
         assert_eq!(
             state_response,
             StateResponse {
-                global_index: Decimal::zero(),
                 total_balance: Uint128::from(0u128),
-                prev_reward_balance: Uint128::zero()
+                total_unbonding: Uint128::zero(),
+                reward_indices: vec![RewardIndex {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    global_index: Decimal::zero(),
+                    prev_reward_balance: Uint128::zero(),
+                    undistributed_rewards: Uint128::zero(),
+                }],
             }
         );
     }
 
     #[test]
-    fn update_global_index() {
-        let mut deps = mock_dependencies(&[Coin {
-            denom: "uusd".to_string(),
-            amount: Uint128::from(100u128),
-        }]);
-
+    fn update_config() {
+        let mut deps = mock_dependencies(&[]);
         let init_msg = default_init();
         let env = mock_env();
-        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
-        instantiate(deps.as_mut(), env.clone(), info.clone(), init_msg).unwrap();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
 
-        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        let msg = ExecuteMsg::UpdateConfig {
+            unbonding_period: Some(Duration::Time(86400)),
+            reward_denoms: None,
+            reward_token: None,
+            guardian: None,
+            distribution_period: None,
+            instant_unbond_penalty: None,
+            disable_instant_unbond: None,
+            treasury: None,
+            remove_treasury: None,
+            max_claims: None,
+        };
 
-        // Failed zero staking balance
-        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+        // Failed unauthorized
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
         match res {
-            Err(StdError::GenericErr { msg, .. }) => assert_eq!(msg, "No asset is bonded by Hub"),
+            Err(ContractError::Unauthorized {}) => {}
             _ => panic!("DO NOT ENTER HERE"),
         }
-        STATE
-            .save(
-                deps.as_mut().storage,
-                &State {
-                    global_index: Decimal::zero(),
-                    total_balance: Uint128::from(100u128),
-                    prev_reward_balance: Uint128::zero(),
-                },
-            )
-            .unwrap();
 
-        // claimed_rewards = 100, total_balance = 100
-        // global_index == 1
-        //handle(&mut deps, env, msg).unwrap();
-        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-        let res = query(deps.as_ref(), env, QueryMsg::State {}).unwrap();
-        let state_response: StateResponse = from_binary(&res).unwrap();
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
         assert_eq!(
-            state_response,
-            StateResponse {
-                global_index: Decimal::one(),
-                total_balance: Uint128::from(100u128),
-                prev_reward_balance: Uint128::from(100u128)
+            res.attributes,
+            vec![
+                attr("action", "update_config"),
+                attr("unbonding_period", "time: 86400"),
+            ]
+        );
+
+        let res = query(deps.as_ref(), env, QueryMsg::Config {}).unwrap();
+        let config_response: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            config_response,
+            ConfigResponse {
+                owner: "owner".to_string(),
+                guardian: Some("guardian".to_string()),
+                cw20_token_addr: MOCK_CW20_CONTRACT_ADDR.to_string(),
+                reward_denoms: vec![DEFAULT_REWARD_DENOM.to_string()],
+                reward_token: None,
+                unbonding_period: Duration::Time(86400),
+                distribution_period: None,
+                instant_unbond_penalty: None,
+                treasury: None,
+                max_claims: 50,
+                weight_function: WeightFunction::default(),
+                group_addr: Some(MOCK_GROUP_CONTRACT_ADDR.to_string()),
             }
         );
     }
 
     #[test]
-    fn increase_balance() {
-        let mut deps = mock_dependencies(&[Coin {
-            denom: DEFAULT_REWARD_DENOM.to_string(),
-            amount: Uint128::from(100u128),
-        }]);
-
+    fn transfer_ownership() {
+        let mut deps = mock_dependencies(&[]);
         let init_msg = default_init();
-        let env = mock_env();
+        let mut env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let msg = ExecuteMsg::ProposeNewOwner {
+            owner: "new_owner".to_string(),
+            expires: Expiration::AtHeight(env.block.height + 100),
+        };
+
+        // Failed unauthorized
         let info = mock_info("addr0000", &[]);
-        instantiate(deps.as_mut(), env.clone(), info.clone(), init_msg).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
 
-        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
-        let receive_msg = receive_stake_msg("addr0000", 100);
+        let info = mock_info("owner", &[]);
+        execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::OwnershipProposal {}).unwrap();
+        let proposal_response: Option<OwnershipProposalResponse> = from_binary(&res).unwrap();
+        assert_eq!(
+            proposal_response,
+            Some(OwnershipProposalResponse {
+                owner: "new_owner".to_string(),
+                expires: Expiration::AtHeight(env.block.height + 100),
+            })
+        );
+
+        // Only the proposed owner can accept
+        let info = mock_info("addr0000", &[]);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            info,
+            ExecuteMsg::AcceptOwnership {},
+        );
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Cancel the proposal
+        let info = mock_info("owner", &[]);
         execute(
             deps.as_mut(),
             env.clone(),
-            info.clone(),
-            receive_msg.clone(),
+            info,
+            ExecuteMsg::CancelOwnershipProposal {},
         )
         .unwrap();
+        let info = mock_info("new_owner", &[]);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            info,
+            ExecuteMsg::AcceptOwnership {},
+        );
+        match res {
+            Err(ContractError::NoOwnershipProposal {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
 
-        let res = query(
-            deps.as_ref(),
+        // Expired proposal cannot be accepted
+        let info = mock_info("owner", &[]);
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        env.block.height += 101;
+        let info = mock_info("new_owner", &[]);
+        let res = execute(
+            deps.as_mut(),
             env.clone(),
-            QueryMsg::Holder {
-                address: "addr0000".to_string(),
-            },
+            info.clone(),
+            ExecuteMsg::AcceptOwnership {},
+        );
+        match res {
+            Err(ContractError::OwnershipProposalExpired {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let msg = ExecuteMsg::ProposeNewOwner {
+            owner: "new_owner".to_string(),
+            expires: Expiration::AtHeight(env.block.height + 100),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg).unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            info,
+            ExecuteMsg::AcceptOwnership {},
         )
         .unwrap();
 
-        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap();
+        let config_response: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(config_response.owner, "new_owner".to_string());
+        let res = query(deps.as_ref(), env, QueryMsg::OwnershipProposal {}).unwrap();
+        let proposal_response: Option<OwnershipProposalResponse> = from_binary(&res).unwrap();
+        assert_eq!(proposal_response, None);
+    }
+
+    #[test]
+    fn pause_operations() {
+        let mut deps = mock_dependencies(&[]);
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let msg = ExecuteMsg::Pause {
+            operations: vec![Operation::Bond, Operation::Claim],
+        };
+
+        // Failed unauthorized
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Guardian can pause
+        let info = mock_info("guardian", &[]);
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::PauseStatus {}).unwrap();
+        let pause_response: PauseStatusResponse = from_binary(&res).unwrap();
         assert_eq!(
-            holder_response,
-            HolderResponse {
-                address: "addr0000".to_string(),
-                balance: Uint128::from(100u128),
-                index: Decimal::zero(),
-                pending_rewards: Decimal::zero(),
+            pause_response,
+            PauseStatusResponse {
+                bond: true,
+                unbond: false,
+                withdraw: false,
+                claim: true,
             }
         );
 
-        // claimed_rewards = 100, total_balance = 100
-        // global_index == 1
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            info,
+            receive_stake_msg("addr0000", 100),
+        );
+        match res {
+            Err(ContractError::Paused {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
         let info = mock_info("addr0000", &[]);
-        let msg = ExecuteMsg::UpdateGlobalIndex {};
-        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            info,
+            ExecuteMsg::ClaimRewards { recipient: None },
+        );
+        match res {
+            Err(ContractError::Paused {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Guardian cannot unpause
+        let msg = ExecuteMsg::Unpause {
+            operations: vec![Operation::Bond],
+        };
+        let info = mock_info("guardian", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let info = mock_info("owner", &[]);
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
         let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
-        execute(deps.as_mut(), env.clone(), info.clone(), receive_msg).unwrap();
-        let res = query(
-            deps.as_ref(),
+        execute(
+            deps.as_mut(),
             env.clone(),
-            QueryMsg::Holder {
-                address: "addr0000".to_string(),
-            },
+            info,
+            receive_stake_msg("addr0000", 100),
         )
         .unwrap();
 
-        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        let res = query(deps.as_ref(), env, QueryMsg::PauseStatus {}).unwrap();
+        let pause_response: PauseStatusResponse = from_binary(&res).unwrap();
         assert_eq!(
-            holder_response,
-            HolderResponse {
-                address: "addr0000".to_string(),
-                balance: Uint128::from(200u128),
-                index: Decimal::one(),
-                pending_rewards: Decimal::from_str("100").unwrap(),
+            pause_response,
+            PauseStatusResponse {
+                bond: false,
+                unbond: false,
+                withdraw: false,
+                claim: true,
             }
         );
     }
 
     #[test]
-    fn increase_balance_with_decimals() {
-        let mut deps = mock_dependencies(&[Coin {
-            denom: DEFAULT_REWARD_DENOM.to_string(),
-            amount: Uint128::from(100000u128),
-        }]);
-
-        let init_msg = default_init();
+    fn migrate_contract() {
+        let mut deps = mock_dependencies(&[]);
         let env = mock_env();
-        let info = mock_info("addr0000", &[]);
-        instantiate(deps.as_mut(), env.clone(), info.clone(), init_msg).unwrap();
 
-        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
-        let receive_msg = receive_stake_msg("addr0000", 11);
-        execute(
-            deps.as_mut(),
+        // v3.0.0 storage layout, without contract version
+        CONFIG_V3
+            .save(
+                &mut deps.storage,
+                &ConfigV3 {
+                    cw20_token_addr: deps.api.addr_canonicalize(MOCK_CW20_CONTRACT_ADDR).unwrap(),
+                    reward_denom: DEFAULT_REWARD_DENOM.to_string(),
+                    unbonding_period: 1000,
+                    group_addr: deps
+                        .api
+                        .addr_canonicalize(MOCK_GROUP_CONTRACT_ADDR)
+                        .unwrap(),
+                },
+            )
+            .unwrap();
+        STATE_V3
+            .save(
+                &mut deps.storage,
+                &StateV3 {
+                    global_index: Decimal::one(),
+                    total_balance: Uint128::from(100u128),
+                    prev_reward_balance: Uint128::from(100u128),
+                },
+            )
+            .unwrap();
+        HOLDERS_V3
+            .save(
+                &mut deps.storage,
+                deps.api.addr_canonicalize("addr0000").unwrap().as_slice(),
+                &HolderV3 {
+                    balance: Uint128::from(100u128),
+                    index: Decimal::zero(),
+                    pending_rewards: Decimal::zero(),
+                },
+            )
+            .unwrap();
+        CLAIMS_V3
+            .save(
+                &mut deps.storage,
+                deps.api.addr_canonicalize("addr0000").unwrap().as_slice(),
+                &vec![ClaimV3 {
+                    amount: Uint128::from(10u128),
+                    release_at: Expiration::AtHeight(env.block.height + 400),
+                }],
+            )
+            .unwrap();
+
+        // Failed missing owner
+        let msg = MigrateMsg {
+            owner: None,
+            guardian: None,
+            weight_function: None,
+            limit: None,
+        };
+        let res = migrate(deps.as_mut(), env.clone(), msg);
+        match res {
+            Err(ContractError::MigrationOwnerRequired {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Failed zero scaling factor
+        let mut msg = MigrateMsg {
+            owner: Some("owner".to_string()),
+            guardian: None,
+            weight_function: Some(WeightFunction {
+                scaling_factor: Uint128::zero(),
+                max_weight: None,
+                square_root: false,
+            }),
+            limit: None,
+        };
+        let res = migrate(deps.as_mut(), env.clone(), msg.clone());
+        match res {
+            Err(ContractError::InvalidWeightFunction {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let weight_function = WeightFunction {
+            scaling_factor: Uint128::from(10u128),
+            max_weight: None,
+            square_root: false,
+        };
+        msg.weight_function = Some(weight_function.clone());
+        let res = migrate(deps.as_mut(), env.clone(), msg.clone()).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "migrate"),
+                attr("previous_version", "3.0.0"),
+                attr("new_version", "4.0.0"),
+            ]
+        );
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap();
+        let config_response: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            config_response,
+            ConfigResponse {
+                owner: "owner".to_string(),
+                guardian: None,
+                cw20_token_addr: MOCK_CW20_CONTRACT_ADDR.to_string(),
+                reward_denoms: vec![DEFAULT_REWARD_DENOM.to_string()],
+                reward_token: None,
+                unbonding_period: Duration::Height(1000),
+                distribution_period: None,
+                instant_unbond_penalty: None,
+                treasury: None,
+                max_claims: 50,
+                weight_function,
+                group_addr: Some(MOCK_GROUP_CONTRACT_ADDR.to_string()),
+            }
+        );
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
+        assert_eq!(state_response.total_unbonding, Uint128::from(10u128));
+        let res = query(
+            deps.as_ref(),
             env.clone(),
-            info.clone(),
-            receive_msg.clone(),
+            QueryMsg::StakedBalanceAtHeight {
+                address: "addr0000".to_string(),
+                height: Some(env.block.height + 1),
+            },
         )
         .unwrap();
+        let balance_response: StakedBalanceAtHeightResponse = from_binary(&res).unwrap();
+        assert_eq!(balance_response.balance, Uint128::from(100u128));
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::TotalWeight {}).unwrap();
+        let total_response: cw4::TotalWeightResponse = from_binary(&res).unwrap();
+        assert_eq!(total_response.weight, 10);
         let res = query(
             deps.as_ref(),
             env.clone(),
@@ -245,104 +548,2608 @@ mod tests {
             },
         )
         .unwrap();
-
         let holder_response: HolderResponse = from_binary(&res).unwrap();
         assert_eq!(
             holder_response,
             HolderResponse {
                 address: "addr0000".to_string(),
-                balance: Uint128::from(11u128),
-                index: Decimal::zero(),
-                pending_rewards: Decimal::zero(),
+                balance: Uint128::from(100u128),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: Decimal::zero(),
+                    pending_rewards: Decimal::zero(),
+                }],
             }
         );
 
-        // claimed_rewards = 100000 , total_balance = 11
-        // global_index == 9077.727272727272727272
-        let info = mock_info("addr0000", &[]);
-        let msg = ExecuteMsg::UpdateGlobalIndex {};
-        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
-
-        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
-        let receive_msg = receive_stake_msg("addr0000", 10);
-        execute(
-            deps.as_mut(),
-            env.clone(),
-            info.clone(),
-            receive_msg.clone(),
-        )
-        .unwrap();
-
         let res = query(
             deps.as_ref(),
             env.clone(),
-            QueryMsg::Holder {
+            QueryMsg::AccruedRewards {
                 address: "addr0000".to_string(),
             },
         )
         .unwrap();
+        let rewards_response: AccruedRewardsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            rewards_response.rewards,
+            vec![Coin {
+                denom: DEFAULT_REWARD_DENOM.to_string(),
+                amount: Uint128::from(100u128),
+            }]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Claims {
+                address: "addr0000".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let claims_response: ClaimsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            claims_response.claims,
+            vec![Claim {
+                id: 1,
+                amount: Uint128::from(10u128),
+                release_at: Expiration::AtHeight(env.block.height + 400),
+                created_at_height: env.block.height - 600,
+                created_at_time: None,
+                unbonding_period: Duration::Height(1000),
+            }]
+        );
+        assert_eq!(
+            CLAIMS_V3
+                .may_load(
+                    &deps.storage,
+                    deps.api.addr_canonicalize("addr0000").unwrap().as_slice(),
+                )
+                .unwrap(),
+            None
+        );
+
+        let version = get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.version, "4.0.0");
+
+        // Failed downgrade
+        set_contract_version(
+            deps.as_mut().storage,
+            "crates.io:loterra-staking-contract",
+            "99.0.0",
+        )
+        .unwrap();
+        let res = migrate(deps.as_mut(), env.clone(), msg.clone());
+        match res {
+            Err(ContractError::CannotMigrateVersion { previous_version }) => {
+                assert_eq!(previous_version, "99.0.0")
+            }
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Failed foreign contract
+        set_contract_version(deps.as_mut().storage, "crates.io:cw20-base", "0.9.0").unwrap();
+        let res = migrate(deps.as_mut(), env, msg);
+        match res {
+            Err(ContractError::CannotMigrate { previous_contract }) => {
+                assert_eq!(previous_contract, "crates.io:cw20-base")
+            }
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+    }
+
+    #[test]
+    fn migrate_contract_paged() {
+        let mut deps = mock_dependencies(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(100u128),
+        }]);
+        let env = mock_env();
+
+        // v3.0.0 storage layout with two holders and their claims
+        CONFIG_V3
+            .save(
+                &mut deps.storage,
+                &ConfigV3 {
+                    cw20_token_addr: deps.api.addr_canonicalize(MOCK_CW20_CONTRACT_ADDR).unwrap(),
+                    reward_denom: DEFAULT_REWARD_DENOM.to_string(),
+                    unbonding_period: 1000,
+                    group_addr: deps
+                        .api
+                        .addr_canonicalize(MOCK_GROUP_CONTRACT_ADDR)
+                        .unwrap(),
+                },
+            )
+            .unwrap();
+        STATE_V3
+            .save(
+                &mut deps.storage,
+                &StateV3 {
+                    global_index: Decimal::one(),
+                    total_balance: Uint128::from(150u128),
+                    prev_reward_balance: Uint128::from(100u128),
+                },
+            )
+            .unwrap();
+        for (address, balance) in [("addr0000", 100u128), ("addr0001", 50u128)] {
+            let address_raw = deps.api.addr_canonicalize(address).unwrap();
+            HOLDERS_V3
+                .save(
+                    &mut deps.storage,
+                    address_raw.as_slice(),
+                    &HolderV3 {
+                        balance: Uint128::from(balance),
+                        index: Decimal::one(),
+                        pending_rewards: Decimal::zero(),
+                    },
+                )
+                .unwrap();
+            CLAIMS_V3
+                .save(
+                    &mut deps.storage,
+                    address_raw.as_slice(),
+                    &vec![ClaimV3 {
+                        amount: Uint128::from(10u128),
+                        release_at: Expiration::AtHeight(env.block.height + 400),
+                    }],
+                )
+                .unwrap();
+        }
+
+        // a single holder is migrated right away
+        let msg = MigrateMsg {
+            owner: Some("owner".to_string()),
+            guardian: None,
+            weight_function: None,
+            limit: Some(1),
+        };
+        migrate(deps.as_mut(), env.clone(), msg).unwrap();
+
+        // Failed contract locked until the migration is complete
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        let res = execute(deps.as_mut(), env.clone(), info, receive_msg.clone());
+        match res {
+            Err(ContractError::MigrationPending {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Failed holders and claims not readable until the migration is complete
+        let pending_queries = vec![
+            QueryMsg::State {},
+            QueryMsg::Holder {
+                address: "addr0001".to_string(),
+            },
+            QueryMsg::Holders {
+                start_after: None,
+                limit: None,
+            },
+            QueryMsg::AccruedRewards {
+                address: "addr0001".to_string(),
+            },
+            QueryMsg::Claims {
+                address: "addr0001".to_string(),
+                start_after: None,
+                limit: None,
+            },
+            QueryMsg::AllClaims {
+                start_after: None,
+                limit: None,
+            },
+            QueryMsg::Member {
+                addr: "addr0001".to_string(),
+                at_height: None,
+            },
+            QueryMsg::ListMembers {
+                start_after: None,
+                limit: None,
+            },
+            QueryMsg::GroupDivergence {
+                start_after: None,
+                limit: None,
+            },
+        ];
+        for pending_query in pending_queries.clone() {
+            let res = query(deps.as_ref(), env.clone(), pending_query);
+            match res {
+                Err(StdError::GenericErr { msg, .. }) => {
+                    assert_eq!(msg, ContractError::MigrationPending {}.to_string())
+                }
+                _ => panic!("DO NOT ENTER HERE"),
+            }
+        }
+        query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap();
+
+        let msg = ExecuteMsg::ContinueMigration { limit: Some(10) };
+
+        // Failed unauthorized
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "continue_migration"),
+                attr("complete", "true"),
+            ]
+        );
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
+        assert_eq!(state_response.total_unbonding, Uint128::from(20u128));
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::TotalWeight {}).unwrap();
+        let total_response: cw4::TotalWeightResponse = from_binary(&res).unwrap();
+        assert_eq!(total_response.weight, 150);
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Holders {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let holders_response: HoldersResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            holders_response
+                .holders
+                .iter()
+                .map(|holder| (holder.address.as_str(), holder.balance))
+                .collect::<Vec<_>>(),
+            vec![
+                ("addr0000", Uint128::from(100u128)),
+                ("addr0001", Uint128::from(50u128)),
+            ]
+        );
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AllClaims {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let claims_response: AllClaimsResponse = from_binary(&res).unwrap();
+        assert_eq!(claims_response.claims.len(), 2);
+
+        for pending_query in pending_queries {
+            query(deps.as_ref(), env.clone(), pending_query).unwrap();
+        }
+
+        // Failed nothing left to migrate
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
+        match res {
+            Err(ContractError::NoPendingMigration {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        execute(deps.as_mut(), env, info, receive_msg).unwrap();
+    }
+
+    #[test]
+    fn update_global_index() {
+        let mut deps = mock_dependencies(&[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(100u128),
+        }]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
+        instantiate(deps.as_mut(), env.clone(), info.clone(), init_msg).unwrap();
+
+        let msg = ExecuteMsg::UpdateGlobalIndex {};
+
+        // rewards received while nothing is bonded are held
+        execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            state_response.reward_indices,
+            vec![RewardIndex {
+                denom: DEFAULT_REWARD_DENOM.to_string(),
+                global_index: Decimal::zero(),
+                prev_reward_balance: Uint128::from(100u128),
+                undistributed_rewards: Uint128::from(100u128),
+            }]
+        );
+
+        STATE
+            .save(
+                deps.as_mut().storage,
+                &State {
+                    total_balance: Uint128::from(100u128),
+                    total_unbonding: Uint128::zero(),
+                    reward_indices: vec![RewardIndex {
+                        denom: DEFAULT_REWARD_DENOM.to_string(),
+                        global_index: Decimal::zero(),
+                        prev_reward_balance: Uint128::zero(),
+                        undistributed_rewards: Uint128::zero(),
+                    }],
+                },
+            )
+            .unwrap();
+
+        // claimed_rewards = 100, total_balance = 100
+        // global_index == 1
+        //handle(&mut deps, env, msg).unwrap();
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let res = query(deps.as_ref(), env, QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            state_response,
+            StateResponse {
+                total_balance: Uint128::from(100u128),
+                total_unbonding: Uint128::zero(),
+                reward_indices: vec![RewardIndex {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    global_index: Decimal::one(),
+                    prev_reward_balance: Uint128::from(100u128),
+                    undistributed_rewards: Uint128::zero(),
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn auto_update_global_index() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        deps.querier.update_balance(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(100u128),
+        }]);
+
+        // rewards are indexed before addr0001 bonds, without UpdateGlobalIndex
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0001", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AccruedRewards {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+        let rewards_response: AccruedRewardsResponse = from_binary(&res).unwrap();
+        assert_eq!(rewards_response.rewards[0].amount, Uint128::from(100u128));
+
+        let res = query(
+            deps.as_ref(),
+            env,
+            QueryMsg::AccruedRewards {
+                address: "addr0001".to_string(),
+            },
+        )
+        .unwrap();
+        let rewards_response: AccruedRewardsResponse = from_binary(&res).unwrap();
+        assert_eq!(rewards_response.rewards[0].amount, Uint128::zero());
+    }
+
+    #[test]
+    fn bond_stake_errors() {
+        let mut deps = mock_dependencies(&[]);
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        // Failed receive from another cw20
+        let info = mock_info("other_token", &[]);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            info,
+            receive_stake_msg("addr0000", 100),
+        );
+        match res {
+            Err(ContractError::InvalidCw20Token {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Failed zero amount
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let res = execute(deps.as_mut(), env, info, receive_stake_msg("addr0000", 0));
+        match res {
+            Err(ContractError::ZeroAmount {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+    }
+
+    #[test]
+    fn bond_stake_for_recipient() {
+        let mut deps = mock_dependencies(&[]);
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let bond_msg = |recipient: &str| {
+            ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: "vesting".to_string(),
+                amount: Uint128::from(100u128),
+                msg: to_binary(&ReceiveMsg::BondStake {
+                    recipient: Some(recipient.to_string()),
+                })
+                .unwrap(),
+            })
+        };
+
+        // Failed invalid recipient
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, bond_msg("ok"));
+        match res {
+            Err(ContractError::Std(_)) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, bond_msg("addr0000")).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "bond_stake"),
+                attr("funder", "vesting"),
+                attr("holder_address", "addr0000"),
+                attr("amount", "100"),
+            ]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Holder {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        assert_eq!(holder_response.balance, Uint128::from(100u128));
+
+        let res = query(
+            deps.as_ref(),
+            env,
+            QueryMsg::Holder {
+                address: "vesting".to_string(),
+            },
+        )
+        .unwrap();
+        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        assert_eq!(holder_response.balance, Uint128::zero());
+    }
+
+    #[test]
+    fn increase_balance() {
+        let mut deps = mock_dependencies(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(100u128),
+        }]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("addr0000", &[]);
+        instantiate(deps.as_mut(), env.clone(), info.clone(), init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            info.clone(),
+            receive_msg.clone(),
+        )
+        .unwrap();
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Holder {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+
+        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            holder_response,
+            HolderResponse {
+                address: "addr0000".to_string(),
+                balance: Uint128::from(100u128),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: Decimal::zero(),
+                    pending_rewards: Decimal::zero(),
+                }],
+            }
+        );
+
+        // claimed_rewards = 100, total_balance = 100
+        // global_index == 1
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        execute(deps.as_mut(), env.clone(), info.clone(), receive_msg).unwrap();
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Holder {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+
+        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            holder_response,
+            HolderResponse {
+                address: "addr0000".to_string(),
+                balance: Uint128::from(200u128),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: Decimal::one(),
+                    pending_rewards: Decimal::from_str("100").unwrap(),
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn increase_balance_with_decimals() {
+        let mut deps = mock_dependencies(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(100000u128),
+        }]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("addr0000", &[]);
+        instantiate(deps.as_mut(), env.clone(), info.clone(), init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 11);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            info.clone(),
+            receive_msg.clone(),
+        )
+        .unwrap();
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Holder {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+
+        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            holder_response,
+            HolderResponse {
+                address: "addr0000".to_string(),
+                balance: Uint128::from(11u128),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: Decimal::zero(),
+                    pending_rewards: Decimal::zero(),
+                }],
+            }
+        );
+
+        // claimed_rewards = 100000 , total_balance = 11
+        // global_index == 9077.727272727272727272
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 10);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            info.clone(),
+            receive_msg.clone(),
+        )
+        .unwrap();
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Holder {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+
+        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        let index = decimal_multiplication_in_256(
+            Decimal::from_ratio(Uint128::from(100000u128), Uint128::from(11u128)),
+            Decimal::one(),
+        );
+        let user_pend_reward = decimal_multiplication_in_256(
+            Decimal::from_str("11").unwrap(),
+            decimal_subtraction_in_256(holder_response.rewards[0].index, Decimal::zero()),
+        );
+        assert_eq!(
+            holder_response,
+            HolderResponse {
+                address: "addr0000".to_string(),
+                balance: Uint128::from(21u128),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index,
+                    pending_rewards: user_pend_reward,
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn unbond_stake() {
+        let mut deps = mock_dependencies(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(100u128),
+        }]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("addr0000", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(100u128),
+        };
+
+        // Failed underflow
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+        match res {
+            Err(ContractError::InsufficientStake { available }) => {
+                assert_eq!(available, Uint128::zero())
+            }
+            _ => panic!("DO NOT ENTER HERE"),
+        };
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        // claimed_rewards = 100, total_balance = 100
+        // global_index == 1
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(100u128),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Holder {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+
+        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            holder_response,
+            HolderResponse {
+                address: "addr0000".to_string(),
+                balance: Uint128::zero(),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: Decimal::one(),
+                    pending_rewards: Decimal::from_str("100").unwrap(),
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn unbond_stake_max_claims() {
+        let mut deps = mock_dependencies(&[]);
+
+        // Failed no claims allowed
+        let mut init_msg = default_init();
+        init_msg.max_claims = Some(0);
+        let mut env = mock_env();
+        let info = mock_info("owner", &[]);
+        let res = instantiate(deps.as_mut(), env.clone(), info, init_msg.clone());
+        match res {
+            Err(ContractError::InvalidMaxClaims {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        init_msg.max_claims = Some(2);
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let update_msg = ExecuteMsg::UpdateConfig {
+            unbonding_period: None,
+            reward_denoms: None,
+            reward_token: None,
+            guardian: None,
+            distribution_period: None,
+            instant_unbond_penalty: None,
+            disable_instant_unbond: None,
+            treasury: None,
+            remove_treasury: None,
+            max_claims: Some(0),
+        };
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, update_msg);
+        match res {
+            Err(ContractError::InvalidMaxClaims {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(10u128),
+        };
+
+        // claims releasing at the same height are merged
+        let info = mock_info("addr0000", &[]);
+        execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+        assert_eq!(res.attributes[3], attr("claim_id", "1"));
+
+        env.block.height += 1;
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+        assert_eq!(res.attributes[3], attr("claim_id", "2"));
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Claims {
+                address: "addr0000".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let claims_response: ClaimsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            claims_response
+                .claims
+                .iter()
+                .map(|claim| (claim.id, claim.amount))
+                .collect::<Vec<_>>(),
+            vec![(1, Uint128::from(20u128)), (2, Uint128::from(10u128))]
+        );
+
+        // Failed too many claims
+        env.block.height += 1;
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+        match res {
+            Err(ContractError::TooManyClaims { max_claims }) => assert_eq!(max_claims, 2),
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // withdrawn claims are no longer counted
+        env.block.height += 1000;
+        let info = mock_info("addr0000", &[]);
+        let withdraw_msg = ExecuteMsg::WithdrawStake {
+            cap: None,
+            recipient: None,
+        };
+        execute(deps.as_mut(), env.clone(), info, withdraw_msg).unwrap();
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(res.attributes[3], attr("claim_id", "3"));
+    }
+
+    #[test]
+    fn query_claims_pagination() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let mut env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        for holder in ["addr0000", "addr0001"] {
+            let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+            let receive_msg = receive_stake_msg(holder, 100);
+            execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+        }
+
+        // claim ids 1 and 3 for addr0000, 2 for addr0001
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(10u128),
+        };
+        for holder in ["addr0000", "addr0001", "addr0000"] {
+            let info = mock_info(holder, &[]);
+            execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+            env.block.height += 1;
+        }
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
+        assert_eq!(state_response.total_balance, Uint128::from(170u128));
+        assert_eq!(state_response.total_unbonding, Uint128::from(30u128));
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Claims {
+                address: "addr0000".to_string(),
+                start_after: Some(1),
+                limit: None,
+            },
+        )
+        .unwrap();
+        let claims_response: ClaimsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            claims_response
+                .claims
+                .iter()
+                .map(|claim| claim.id)
+                .collect::<Vec<_>>(),
+            vec![3]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AllClaims {
+                start_after: None,
+                limit: Some(2),
+            },
+        )
+        .unwrap();
+        let all_claims_response: AllClaimsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            all_claims_response
+                .claims
+                .iter()
+                .map(|holder_claim| (holder_claim.address.as_str(), holder_claim.claim.id))
+                .collect::<Vec<_>>(),
+            vec![("addr0000", 1), ("addr0000", 3)]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AllClaims {
+                start_after: Some(("addr0000".to_string(), 3)),
+                limit: Some(2),
+            },
+        )
+        .unwrap();
+        let all_claims_response: AllClaimsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            all_claims_response
+                .claims
+                .iter()
+                .map(|holder_claim| (holder_claim.address.as_str(), holder_claim.claim.id))
+                .collect::<Vec<_>>(),
+            vec![("addr0001", 2)]
+        );
+
+        // rebonded and withdrawn stake is no longer unbonding
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::Rebond {
+            amount: Some(Uint128::from(5u128)),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        env.block.height += 1000;
+        let info = mock_info("addr0001", &[]);
+        let msg = ExecuteMsg::WithdrawStake {
+            cap: None,
+            recipient: None,
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), env, QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
+        assert_eq!(state_response.total_balance, Uint128::from(175u128));
+        assert_eq!(state_response.total_unbonding, Uint128::from(15u128));
+    }
+
+    #[test]
+    fn transfer_stake() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        // global_index == 1
+        deps.querier.update_balance(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(100u128),
+        }]);
+
+        // Failed transfer to self
+        let msg = ExecuteMsg::TransferStake {
+            recipient: "addr0000".to_string(),
+            amount: Uint128::from(40u128),
+        };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
+        match res {
+            Err(ContractError::SelfTransfer {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Failed more than the staked balance
+        let msg = ExecuteMsg::TransferStake {
+            recipient: "addr0001".to_string(),
+            amount: Uint128::from(101u128),
+        };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
+        match res {
+            Err(ContractError::InsufficientStake { available }) => {
+                assert_eq!(available, Uint128::from(100u128))
+            }
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let msg = ExecuteMsg::TransferStake {
+            recipient: "addr0001".to_string(),
+            amount: Uint128::from(40u128),
+        };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.messages[0],
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_GROUP_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&cw4_group::msg::ExecuteMsg::UpdateMembers {
+                    remove: vec![],
+                    add: vec![
+                        cw4::Member {
+                            addr: "addr0000".to_string(),
+                            weight: 60,
+                        },
+                        cw4::Member {
+                            addr: "addr0001".to_string(),
+                            weight: 40,
+                        },
+                    ],
+                })
+                .unwrap(),
+                funds: vec![]
+            }))
+        );
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "transfer_stake"),
+                attr("holder_address", "addr0000"),
+                attr("recipient", "addr0001"),
+                attr("amount", "40"),
+            ]
+        );
+
+        // the rewards accrued before the transfer stay with the sender
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Holders {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let holders_response: HoldersResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            holders_response.holders,
+            vec![
+                HolderResponse {
+                    address: "addr0000".to_string(),
+                    balance: Uint128::from(60u128),
+                    rewards: vec![HolderReward {
+                        denom: DEFAULT_REWARD_DENOM.to_string(),
+                        index: Decimal::one(),
+                        pending_rewards: Decimal::from_ratio(100u128, 1u128),
+                    }],
+                },
+                HolderResponse {
+                    address: "addr0001".to_string(),
+                    balance: Uint128::from(40u128),
+                    rewards: vec![HolderReward {
+                        denom: DEFAULT_REWARD_DENOM.to_string(),
+                        index: Decimal::one(),
+                        pending_rewards: Decimal::zero(),
+                    }],
+                },
+            ]
+        );
+
+        let res = query(deps.as_ref(), env, QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
+        assert_eq!(state_response.total_balance, Uint128::from(100u128));
+    }
+
+    #[test]
+    fn staked_balance_at_height() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let mut env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+        let start = env.block.height;
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        env.block.height += 1;
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(30u128),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        env.block.height += 1;
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::TransferStake {
+            recipient: "addr0001".to_string(),
+            amount: Uint128::from(20u128),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        env.block.height += 1;
+
+        // balances are the ones at the start of each block
+        for (height, balance, recipient_balance, total) in [
+            (start, 0u128, 0u128, 0u128),
+            (start + 1, 100, 0, 100),
+            (start + 2, 70, 0, 70),
+            (start + 3, 50, 20, 70),
+        ] {
+            let res = query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::StakedBalanceAtHeight {
+                    address: "addr0000".to_string(),
+                    height: Some(height),
+                },
+            )
+            .unwrap();
+            let balance_response: StakedBalanceAtHeightResponse = from_binary(&res).unwrap();
+            assert_eq!(
+                balance_response,
+                StakedBalanceAtHeightResponse {
+                    balance: Uint128::from(balance),
+                    height,
+                }
+            );
+
+            let res = query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::StakedBalanceAtHeight {
+                    address: "addr0001".to_string(),
+                    height: Some(height),
+                },
+            )
+            .unwrap();
+            let balance_response: StakedBalanceAtHeightResponse = from_binary(&res).unwrap();
+            assert_eq!(balance_response.balance, Uint128::from(recipient_balance));
+
+            let res = query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::TotalStakedAtHeight {
+                    height: Some(height),
+                },
+            )
+            .unwrap();
+            let total_response: TotalStakedAtHeightResponse = from_binary(&res).unwrap();
+            assert_eq!(
+                total_response,
+                TotalStakedAtHeightResponse {
+                    total: Uint128::from(total),
+                    height,
+                }
+            );
+        }
+
+        // the current height is used without one
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::TotalStakedAtHeight { height: None },
+        )
+        .unwrap();
+        let total_response: TotalStakedAtHeightResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            total_response,
+            TotalStakedAtHeightResponse {
+                total: Uint128::from(70u128),
+                height: env.block.height,
+            }
+        );
+    }
+
+    #[test]
+    fn cw4_queries() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let mut env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        for (holder, amount) in [("addr0000", 100), ("addr0001", 50), ("addr0002", 10)] {
+            let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+            let receive_msg = receive_stake_msg(holder, amount);
+            execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+        }
+        env.block.height += 1;
+
+        // holders without stake are no longer members
+        let info = mock_info("addr0002", &[]);
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(10u128),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // cw3 contracts send cw4 queries
+        let cw4_query =
+            |msg: cw4::Cw4QueryMsg| -> QueryMsg { from_binary(&to_binary(&msg).unwrap()).unwrap() };
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            cw4_query(cw4::Cw4QueryMsg::Member {
+                addr: "addr0001".to_string(),
+                at_height: None,
+            }),
+        )
+        .unwrap();
+        let member_response: cw4::MemberResponse = from_binary(&res).unwrap();
+        assert_eq!(member_response.weight, Some(50));
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            cw4_query(cw4::Cw4QueryMsg::Member {
+                addr: "addr0002".to_string(),
+                at_height: None,
+            }),
+        )
+        .unwrap();
+        let member_response: cw4::MemberResponse = from_binary(&res).unwrap();
+        assert_eq!(member_response.weight, None);
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            cw4_query(cw4::Cw4QueryMsg::Member {
+                addr: "addr0002".to_string(),
+                at_height: Some(env.block.height),
+            }),
+        )
+        .unwrap();
+        let member_response: cw4::MemberResponse = from_binary(&res).unwrap();
+        assert_eq!(member_response.weight, Some(10));
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            cw4_query(cw4::Cw4QueryMsg::ListMembers {
+                start_after: None,
+                limit: None,
+            }),
+        )
+        .unwrap();
+        let list_response: cw4::MemberListResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            list_response.members,
+            vec![
+                cw4::Member {
+                    addr: "addr0000".to_string(),
+                    weight: 100,
+                },
+                cw4::Member {
+                    addr: "addr0001".to_string(),
+                    weight: 50,
+                },
+            ]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            cw4_query(cw4::Cw4QueryMsg::ListMembers {
+                start_after: Some("addr0000".to_string()),
+                limit: Some(1),
+            }),
+        )
+        .unwrap();
+        let list_response: cw4::MemberListResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            list_response.members,
+            vec![cw4::Member {
+                addr: "addr0001".to_string(),
+                weight: 50,
+            }]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            cw4_query(cw4::Cw4QueryMsg::TotalWeight {}),
+        )
+        .unwrap();
+        let total_response: cw4::TotalWeightResponse = from_binary(&res).unwrap();
+        assert_eq!(total_response.weight, 150);
+
+        // cw4 raw queries read the weights from storage
+        let raw = deps.storage.get(&cw4::member_key("addr0001")).unwrap();
+        assert_eq!(from_slice::<u64>(&raw).unwrap(), 50);
+        assert_eq!(deps.storage.get(&cw4::member_key("addr0002")), None);
+        let raw = deps.storage.get(cw4::TOTAL_KEY.as_bytes()).unwrap();
+        assert_eq!(from_slice::<u64>(&raw).unwrap(), 150);
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            cw4_query(cw4::Cw4QueryMsg::Admin {}),
+        )
+        .unwrap();
+        let admin_response: cw4::AdminResponse = from_binary(&res).unwrap();
+        assert_eq!(admin_response.admin, Some("owner".to_string()));
+
+        let res = query(deps.as_ref(), env, cw4_query(cw4::Cw4QueryMsg::Hooks {})).unwrap();
+        let hooks_response: cw4::HooksResponse = from_binary(&res).unwrap();
+        assert!(hooks_response.hooks.is_empty());
+    }
+
+    #[test]
+    fn weight_function() {
+        let mut deps = mock_dependencies(&[]);
+
+        // Failed zero scaling factor
+        let mut init_msg = default_init();
+        init_msg.weight_function = Some(WeightFunction {
+            scaling_factor: Uint128::zero(),
+            max_weight: None,
+            square_root: false,
+        });
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        let res = instantiate(deps.as_mut(), env.clone(), info, init_msg);
+        match res {
+            Err(ContractError::InvalidWeightFunction {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let mut init_msg = default_init();
+        init_msg.weight_function = Some(WeightFunction {
+            scaling_factor: Uint128::from(100u128),
+            max_weight: Some(8),
+            square_root: true,
+        });
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        // sqrt(10000 / 100) = 10, capped to 8
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 10000);
+        let res = execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+        assert_eq!(
+            res.messages[0],
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_GROUP_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&cw4_group::msg::ExecuteMsg::UpdateMembers {
+                    remove: vec![],
+                    add: vec![cw4::Member {
+                        addr: "addr0000".to_string(),
+                        weight: 8,
+                    }],
+                })
+                .unwrap(),
+                funds: vec![]
+            }))
+        );
+
+        // sqrt(2500 / 100) = 5
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0001", 2500);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::TotalWeight {}).unwrap();
+        let total_response: cw4::TotalWeightResponse = from_binary(&res).unwrap();
+        assert_eq!(total_response.weight, 13);
+
+        // sqrt(2599 / 100) = 5
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(7401u128),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Member {
+                addr: "addr0000".to_string(),
+                at_height: None,
+            },
+        )
+        .unwrap();
+        let member_response: cw4::MemberResponse = from_binary(&res).unwrap();
+        assert_eq!(member_response.weight, Some(5));
+
+        let res = query(deps.as_ref(), env, QueryMsg::TotalWeight {}).unwrap();
+        let total_response: cw4::TotalWeightResponse = from_binary(&res).unwrap();
+        assert_eq!(total_response.weight, 10);
+    }
+
+    #[test]
+    fn weight_overflow() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        // Failed weight above u64 instead of truncating it
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", u64::MAX as u128 + 1);
+        let res = execute(deps.as_mut(), env, info, receive_msg);
+        match res {
+            Err(ContractError::Std(StdError::ConversionOverflow { .. })) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+    }
+
+    #[test]
+    fn sync_group() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        for (holder, amount) in [("addr0000", 100), ("addr0001", 50), ("addr0002", 10)] {
+            let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+            let receive_msg = receive_stake_msg(holder, amount);
+            execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+        }
+        let info = mock_info("addr0002", &[]);
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(10u128),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // the group was updated out of band
+        deps.querier
+            .update_group_members(&[("addr0000", 100), ("addr0001", 40), ("addr0002", 10)]);
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::GroupDivergence {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let divergence_response: GroupDivergenceResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            divergence_response,
+            GroupDivergenceResponse {
+                divergences: vec![
+                    WeightDivergence {
+                        address: "addr0001".to_string(),
+                        weight: Some(50),
+                        group_weight: Some(40),
+                    },
+                    WeightDivergence {
+                        address: "addr0002".to_string(),
+                        weight: None,
+                        group_weight: Some(10),
+                    },
+                ],
+                last_holder: Some("addr0002".to_string()),
+            }
+        );
+
+        // Failed unauthorized
+        let msg = ExecuteMsg::SyncGroup {
+            start_after: None,
+            limit: None,
+        };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let msg = ExecuteMsg::SyncGroup {
+            start_after: None,
+            limit: Some(2),
+        };
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_GROUP_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&cw4_group::msg::ExecuteMsg::UpdateMembers {
+                    remove: vec![],
+                    add: vec![
+                        cw4::Member {
+                            addr: "addr0000".to_string(),
+                            weight: 100,
+                        },
+                        cw4::Member {
+                            addr: "addr0001".to_string(),
+                            weight: 50,
+                        },
+                    ],
+                })
+                .unwrap(),
+                funds: vec![]
+            }))]
+        );
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "sync_group"),
+                attr("synced", "2"),
+                attr("last_holder", "addr0001"),
+            ]
+        );
+
+        // holders without stake are removed
+        let msg = ExecuteMsg::SyncGroup {
+            start_after: Some("addr0001".to_string()),
+            limit: Some(2),
+        };
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_GROUP_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&cw4_group::msg::ExecuteMsg::UpdateMembers {
+                    remove: vec!["addr0002".to_string()],
+                    add: vec![],
+                })
+                .unwrap(),
+                funds: vec![]
+            }))]
+        );
+
+        deps.querier
+            .update_group_members(&[("addr0000", 100), ("addr0001", 50)]);
+        let res = query(
+            deps.as_ref(),
+            env,
+            QueryMsg::GroupDivergence {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let divergence_response: GroupDivergenceResponse = from_binary(&res).unwrap();
+        assert!(divergence_response.divergences.is_empty());
+    }
+
+    #[test]
+    fn group_divergence_after_unbond() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        for (holder, amount) in [("addr0000", 100), ("addr0001", 50)] {
+            let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+            let receive_msg = receive_stake_msg(holder, amount);
+            execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+        }
+
+        // a holder unbonding the whole stake is removed from the group
+        let info = mock_info("addr0001", &[]);
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(50u128),
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_GROUP_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&cw4_group::msg::ExecuteMsg::UpdateMembers {
+                    remove: vec!["addr0001".to_string()],
+                    add: vec![],
+                })
+                .unwrap(),
+                funds: vec![]
+            }))]
+        );
+
+        // the group as left by the sent messages
+        deps.querier.update_group_members(&[("addr0000", 100)]);
+        let res = query(
+            deps.as_ref(),
+            env,
+            QueryMsg::GroupDivergence {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let divergence_response: GroupDivergenceResponse = from_binary(&res).unwrap();
+        assert!(divergence_response.divergences.is_empty());
+    }
+
+    #[test]
+    fn update_group() {
+        let mut deps = mock_dependencies(&[]);
+
+        let mut init_msg = default_init();
+        init_msg.group_addr = None;
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        // no group messages without a group
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        let res = execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+        assert!(res.messages.is_empty());
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::TotalWeight {}).unwrap();
+        let total_response: cw4::TotalWeightResponse = from_binary(&res).unwrap();
+        assert_eq!(total_response.weight, 100);
+
+        // Failed sync without a group
+        let msg = ExecuteMsg::SyncGroup {
+            start_after: None,
+            limit: None,
+        };
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
+        match res {
+            Err(ContractError::NoGroup {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Failed unauthorized
+        let msg = ExecuteMsg::UpdateGroup {
+            group_addr: Some("new_group".to_string()),
+        };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "update_group"),
+                attr("group_addr", "new_group"),
+            ]
+        );
+
+        // the existing holders are synced into the attached group
+        let msg = ExecuteMsg::SyncGroup {
+            start_after: None,
+            limit: None,
+        };
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "new_group".to_string(),
+                msg: to_binary(&cw4_group::msg::ExecuteMsg::UpdateMembers {
+                    remove: vec![],
+                    add: vec![cw4::Member {
+                        addr: "addr0000".to_string(),
+                        weight: 100,
+                    }],
+                })
+                .unwrap(),
+                funds: vec![]
+            }))]
+        );
+
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(10u128),
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "new_group".to_string(),
+                msg: to_binary(&cw4_group::msg::ExecuteMsg::UpdateMembers {
+                    remove: vec![],
+                    add: vec![cw4::Member {
+                        addr: "addr0000".to_string(),
+                        weight: 90,
+                    }],
+                })
+                .unwrap(),
+                funds: vec![]
+            }))]
+        );
+
+        // detach the group
+        let msg = ExecuteMsg::UpdateGroup { group_addr: None };
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(res.attributes[1], attr("group_addr", "none"));
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap();
+        let config_response: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(config_response.group_addr, None);
+
+        let res = query(
+            deps.as_ref(),
+            env,
+            QueryMsg::GroupDivergence {
+                start_after: None,
+                limit: None,
+            },
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn instant_unbond() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        let msg = ExecuteMsg::InstantUnbond {
+            amount: Uint128::from(50u128),
+        };
+
+        // Failed instant unbond disabled
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+        match res {
+            Err(ContractError::InstantUnbondDisabled {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let mut update_msg = ExecuteMsg::UpdateConfig {
+            unbonding_period: None,
+            reward_denoms: None,
+            reward_token: None,
+            guardian: None,
+            distribution_period: None,
+            instant_unbond_penalty: Some(Decimal::one()),
+            disable_instant_unbond: None,
+            treasury: None,
+            remove_treasury: None,
+            max_claims: None,
+        };
+
+        // Failed penalty keeps the whole stake
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, update_msg.clone());
+        match res {
+            Err(ContractError::InvalidPenalty {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        if let ExecuteMsg::UpdateConfig {
+            instant_unbond_penalty,
+            ..
+        } = &mut update_msg
+        {
+            *instant_unbond_penalty = Some(Decimal::percent(10));
+        }
+
+        // Failed nobody can collect the penalty
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, update_msg.clone());
+        match res {
+            Err(ContractError::NoPenaltyRecipient {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        if let ExecuteMsg::UpdateConfig { treasury, .. } = &mut update_msg {
+            *treasury = Some("treasury".to_string());
+        }
+        let info = mock_info("owner", &[]);
+        execute(deps.as_mut(), env.clone(), info, update_msg.clone()).unwrap();
+
+        // the penalty is sent to the treasury
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+        assert_eq!(
+            res.messages[1..],
+            vec![
+                SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: MOCK_CW20_CONTRACT_ADDR.to_string(),
+                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: "treasury".to_string(),
+                        amount: Uint128::from(5u128),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                })),
+                SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: MOCK_CW20_CONTRACT_ADDR.to_string(),
+                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: "addr0000".to_string(),
+                        amount: Uint128::from(45u128),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                })),
+            ]
+        );
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "instant_unbond"),
+                attr("holder_address", "addr0000"),
+                attr("amount", "50"),
+                attr("penalty", "5"),
+            ]
+        );
+
+        // Failed withdraw paused, instant unbond sends the stake out at once
+        let info = mock_info("owner", &[]);
+        let pause_msg = ExecuteMsg::Pause {
+            operations: vec![Operation::Withdraw],
+        };
+        execute(deps.as_mut(), env.clone(), info, pause_msg).unwrap();
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+        match res {
+            Err(ContractError::Paused {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let info = mock_info("owner", &[]);
+        let unpause_msg = ExecuteMsg::Unpause {
+            operations: vec![Operation::Withdraw],
+        };
+        execute(deps.as_mut(), env.clone(), info, unpause_msg).unwrap();
+
+        // the penalty is rounded up
+        let info = mock_info("addr0000", &[]);
+        let round_msg = ExecuteMsg::InstantUnbond {
+            amount: Uint128::from(15u128),
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, round_msg).unwrap();
+        assert_eq!(res.attributes[3], attr("penalty", "2"));
+
+        // Failed set and unset the treasury at once
+        if let ExecuteMsg::UpdateConfig {
+            instant_unbond_penalty,
+            remove_treasury,
+            ..
+        } = &mut update_msg
+        {
+            *instant_unbond_penalty = None;
+            *remove_treasury = Some(true);
+        }
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, update_msg.clone());
+        match res {
+            Err(ContractError::ConflictingUpdate { field }) => assert_eq!(field, "treasury"),
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Failed unset the treasury still collecting the penalty
+        if let ExecuteMsg::UpdateConfig { treasury, .. } = &mut update_msg {
+            *treasury = None;
+        }
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, update_msg.clone());
+        match res {
+            Err(ContractError::NoPenaltyRecipient {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // unset the treasury and disable instant unbond
+        if let ExecuteMsg::UpdateConfig {
+            disable_instant_unbond,
+            ..
+        } = &mut update_msg
+        {
+            *disable_instant_unbond = Some(true);
+        }
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, update_msg).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "update_config"),
+                attr("instant_unbond_penalty", "none"),
+                attr("treasury", "none"),
+            ]
+        );
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap();
+        let config: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(config.instant_unbond_penalty, None);
+        assert_eq!(config.treasury, None);
+
+        // Failed instant unbond disabled again
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
+        match res {
+            Err(ContractError::InstantUnbondDisabled {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // Failed no treasury and rewards not paid in the staking token
+        let mut deps = mock_dependencies(&[]);
+        let mut init_msg = default_init();
+        init_msg.instant_unbond_penalty = Some(Decimal::percent(10));
+        let info = mock_info("owner", &[]);
+        let res = instantiate(deps.as_mut(), env.clone(), info, init_msg.clone());
+        match res {
+            Err(ContractError::NoPenaltyRecipient {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // without a treasury the penalty goes to the remaining stakers
+        init_msg.reward_token = Some(Addr::unchecked(MOCK_CW20_CONTRACT_ADDR));
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0001", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        let msg = ExecuteMsg::InstantUnbond {
+            amount: Uint128::from(100u128),
+        };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 2);
+
+        let res = query(
+            deps.as_ref(),
+            env,
+            QueryMsg::AccruedRewards {
+                address: "addr0001".to_string(),
+            },
+        )
+        .unwrap();
+        let rewards_response: AccruedRewardsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            rewards_response.rewards[1],
+            Coin {
+                denom: MOCK_CW20_CONTRACT_ADDR.to_string(),
+                amount: Uint128::from(10u128),
+            }
+        );
+    }
+
+    #[test]
+    fn rebond_stake() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let mut env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        // Failed no claims
+        let msg = ExecuteMsg::Rebond { amount: None };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
+        match res {
+            Err(ContractError::NothingToRebond {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // a mature claim cannot be rebonded
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(10u128),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        env.block.height += 1000;
+
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(30u128),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        env.block.height += 10;
+
+        let info = mock_info("addr0000", &[]);
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(20u128),
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Failed more than the immature claims
+        let msg = ExecuteMsg::Rebond {
+            amount: Some(Uint128::from(60u128)),
+        };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
+        match res {
+            Err(ContractError::InsufficientClaims { available }) => {
+                assert_eq!(available, Uint128::from(50u128))
+            }
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // the newest claim is rebonded first
+        let msg = ExecuteMsg::Rebond {
+            amount: Some(Uint128::from(30u128)),
+        };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "rebond_stake"),
+                attr("holder_address", "addr0000"),
+                attr("amount", "30"),
+            ]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Claims {
+                address: "addr0000".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let claims_response: ClaimsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            claims_response.claims,
+            vec![
+                Claim {
+                    id: 1,
+                    amount: Uint128::from(10u128),
+                    release_at: Expiration::AtHeight(env.block.height - 1010 + 1000),
+                    created_at_height: env.block.height - 1010,
+                    created_at_time: Some(env.block.time),
+                    unbonding_period: Duration::Height(1000),
+                },
+                Claim {
+                    id: 2,
+                    amount: Uint128::from(20u128),
+                    release_at: Expiration::AtHeight(env.block.height - 10 + 1000),
+                    created_at_height: env.block.height - 10,
+                    created_at_time: Some(env.block.time),
+                    unbonding_period: Duration::Height(1000),
+                },
+            ]
+        );
+
+        let msg = ExecuteMsg::Rebond { amount: None };
+        let info = mock_info("addr0000", &[]);
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let res = query(
+            deps.as_ref(),
+            env,
+            QueryMsg::Holder {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        assert_eq!(holder_response.balance, Uint128::from(90u128));
+    }
+
+    #[test]
+    fn claim_rewards() {
+        let mut deps = mock_dependencies(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(100u128),
+        }]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info(MOCK_TOKEN_CONTRACT_ADDR, &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Holder {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+
+        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            holder_response,
+            HolderResponse {
+                address: "addr0000".to_string(),
+                balance: Uint128::from(100u128),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: Decimal::zero(),
+                    pending_rewards: Decimal::zero(),
+                }],
+            }
+        );
 
-        let holder_response: HolderResponse = from_binary(&res).unwrap();
-        let index = decimal_multiplication_in_256(
-            Decimal::from_ratio(Uint128::from(100000u128), Uint128::from(11u128)),
-            Decimal::one(),
+        // claimed_rewards = 100, total_balance = 100
+        // global_index == 1
+        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
+        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let msg = ExecuteMsg::ClaimRewards { recipient: None };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        assert_eq!(
+            res.messages[0],
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "addr0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(99u128), // 1% tax
+                },]
+            }))
         );
-        let user_pend_reward = decimal_multiplication_in_256(
-            Decimal::from_str("11").unwrap(),
-            decimal_subtraction_in_256(holder_response.index, Decimal::zero()),
+
+        // Set recipient
+        // claimed_rewards = 100, total_balance = 100
+        // global_index == 1
+        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
+        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let msg = ExecuteMsg::ClaimRewards {
+            recipient: Some(Addr::unchecked("addr0001").to_string()),
+        };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.messages[0],
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "addr0001".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(99u128), // 1% tax
+                },]
+            }))
+        );
+    }
+
+    #[test]
+    fn claim_rewards_multiple_denoms() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        // Failed removing a reward denom
+        let msg = ExecuteMsg::UpdateConfig {
+            unbonding_period: None,
+            reward_denoms: Some(vec!["uluna".to_string()]),
+            reward_token: None,
+            guardian: None,
+            distribution_period: None,
+            instant_unbond_penalty: None,
+            disable_instant_unbond: None,
+            treasury: None,
+            remove_treasury: None,
+            max_claims: None,
+        };
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
+        match res {
+            Err(ContractError::RewardDenomRemoved { denom }) => {
+                assert_eq!(denom, DEFAULT_REWARD_DENOM)
+            }
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let msg = ExecuteMsg::UpdateConfig {
+            unbonding_period: None,
+            reward_denoms: Some(vec![DEFAULT_REWARD_DENOM.to_string(), "uluna".to_string()]),
+            reward_token: None,
+            guardian: None,
+            distribution_period: None,
+            instant_unbond_penalty: None,
+            disable_instant_unbond: None,
+            treasury: None,
+            remove_treasury: None,
+            max_claims: None,
+        };
+        let info = mock_info("owner", &[]);
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        deps.querier.update_balance(&[
+            Coin {
+                denom: DEFAULT_REWARD_DENOM.to_string(),
+                amount: Uint128::from(100u128),
+            },
+            Coin {
+                denom: "uluna".to_string(),
+                amount: Uint128::from(50u128),
+            },
+        ]);
+
+        // uusd global_index == 1, uluna global_index == 0.5
+        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
+        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "update_global_index"),
+                attr("claimed_rewards", "100uusd,50uluna"),
+            ]
         );
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AccruedRewards {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+        let rewards_response: AccruedRewardsResponse = from_binary(&res).unwrap();
         assert_eq!(
-            holder_response,
-            HolderResponse {
+            rewards_response,
+            AccruedRewardsResponse {
+                rewards: vec![
+                    Coin {
+                        denom: DEFAULT_REWARD_DENOM.to_string(),
+                        amount: Uint128::from(100u128),
+                    },
+                    Coin {
+                        denom: "uluna".to_string(),
+                        amount: Uint128::from(50u128),
+                    },
+                ],
+            }
+        );
+
+        let msg = ExecuteMsg::ClaimRewards { recipient: None };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0],
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "addr0000".to_string(),
+                amount: vec![
+                    Coin {
+                        denom: "uluna".to_string(),
+                        amount: Uint128::from(49u128), // 1% tax
+                    },
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(99u128), // 1% tax
+                    },
+                ]
+            }))
+        );
+
+        let res = query(deps.as_ref(), env, QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            state_response,
+            StateResponse {
+                total_balance: Uint128::from(100u128),
+                total_unbonding: Uint128::zero(),
+                reward_indices: vec![
+                    RewardIndex {
+                        denom: DEFAULT_REWARD_DENOM.to_string(),
+                        global_index: Decimal::one(),
+                        prev_reward_balance: Uint128::zero(),
+                        undistributed_rewards: Uint128::zero(),
+                    },
+                    RewardIndex {
+                        denom: "uluna".to_string(),
+                        global_index: Decimal::percent(50),
+                        prev_reward_balance: Uint128::zero(),
+                        undistributed_rewards: Uint128::zero(),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn claim_rewards_cw20_token() {
+        let mut deps = mock_dependencies(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(100u128),
+        }]);
+
+        let mut init_msg = default_init();
+        init_msg.reward_token = Some(Addr::unchecked(MOCK_TOKEN_CONTRACT_ADDR));
+        let env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let distribute_msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "lottery_contract".to_string(),
+            amount: Uint128::from(200u128),
+            msg: to_binary(&ReceiveMsg::DistributeRewards {}).unwrap(),
+        });
+
+        // rewards deposited while nothing is bonded are held
+        let info = mock_info(MOCK_TOKEN_CONTRACT_ADDR, &[]);
+        execute(deps.as_mut(), env.clone(), info, distribute_msg.clone()).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        // Failed rewards from another cw20
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, distribute_msg.clone());
+        match res {
+            Err(ContractError::InvalidRewardToken {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        // held and deposited rewards, token global_index == 4
+        let info = mock_info(MOCK_TOKEN_CONTRACT_ADDR, &[]);
+        execute(deps.as_mut(), env.clone(), info, distribute_msg).unwrap();
+
+        // uusd global_index == 1
+        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
+        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            state_response,
+            StateResponse {
+                total_balance: Uint128::from(100u128),
+                total_unbonding: Uint128::zero(),
+                reward_indices: vec![
+                    RewardIndex {
+                        denom: DEFAULT_REWARD_DENOM.to_string(),
+                        global_index: Decimal::one(),
+                        prev_reward_balance: Uint128::from(100u128),
+                        undistributed_rewards: Uint128::zero(),
+                    },
+                    RewardIndex {
+                        denom: MOCK_TOKEN_CONTRACT_ADDR.to_string(),
+                        global_index: Decimal::from_ratio(4u128, 1u128),
+                        prev_reward_balance: Uint128::from(400u128),
+                        undistributed_rewards: Uint128::zero(),
+                    },
+                ],
+            }
+        );
+
+        let msg = ExecuteMsg::ClaimRewards { recipient: None };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: MOCK_TOKEN_CONTRACT_ADDR.to_string(),
+                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: "addr0000".to_string(),
+                        amount: Uint128::from(400u128),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                })),
+                SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "addr0000".to_string(),
+                    amount: vec![Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(99u128), // 1% tax
+                    }]
+                })),
+            ]
+        );
+    }
+
+    #[test]
+    fn stream_rewards() {
+        let mut deps = mock_dependencies(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(1000u128),
+        }]);
+
+        let mut init_msg = default_init();
+        init_msg.distribution_period = Some(100);
+        let mut env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        // 1000uusd released over 100 seconds, nothing released yet
+        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
+        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            state_response.reward_indices[0].global_index,
+            Decimal::zero()
+        );
+
+        // half of the rewards released to addr0000
+        env.block.time = env.block.time.plus_seconds(50);
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AccruedRewards {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+        let rewards_response: AccruedRewardsResponse = from_binary(&res).unwrap();
+        assert_eq!(rewards_response.rewards[0].amount, Uint128::from(500u128));
+
+        // addr0001 only shares the rewards released after bonding
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0001", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        // nothing more is released after the period finishes
+        env.block.time = env.block.time.plus_seconds(100);
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AccruedRewards {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+        let rewards_response: AccruedRewardsResponse = from_binary(&res).unwrap();
+        assert_eq!(rewards_response.rewards[0].amount, Uint128::from(750u128));
+
+        let msg = ExecuteMsg::ClaimRewards { recipient: None };
+        let info = mock_info("addr0001", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "claim_reward"),
+                attr("holder_address", "addr0001"),
+                attr("rewards", "250uusd"),
+            ]
+        );
+
+        // switch back to indexing rewards at once
+        let msg = ExecuteMsg::UpdateConfig {
+            unbonding_period: None,
+            reward_denoms: None,
+            reward_token: None,
+            guardian: None,
+            distribution_period: Some(0),
+            instant_unbond_penalty: None,
+            disable_instant_unbond: None,
+            treasury: None,
+            remove_treasury: None,
+            max_claims: None,
+        };
+        let info = mock_info("owner", &[]);
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), env, QueryMsg::Config {}).unwrap();
+        let config_response: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(config_response.distribution_period, None);
+    }
+
+    #[test]
+    fn stream_rewards_top_up() {
+        let mut deps = mock_dependencies(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(1000u128),
+        }]);
+
+        let mut init_msg = default_init();
+        init_msg.distribution_period = Some(100);
+        let mut env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        // 1000uusd released over 100 seconds
+        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
+        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+
+        // a dust top up is released with the running stream, without restarting it
+        env.block.time = env.block.time.plus_seconds(50);
+        deps.querier.update_balance(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(1010u128),
+        }]);
+        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
+        execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(25);
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AccruedRewards {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+        let rewards_response: AccruedRewardsResponse = from_binary(&res).unwrap();
+        assert_eq!(rewards_response.rewards[0].amount, Uint128::from(755u128));
+
+        // a larger top up restarts the distribution period
+        deps.querier.update_balance(&[Coin {
+            denom: DEFAULT_REWARD_DENOM.to_string(),
+            amount: Uint128::from(2010u128),
+        }]);
+        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(50);
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AccruedRewards {
                 address: "addr0000".to_string(),
-                balance: Uint128::from(21u128),
-                index,
-                pending_rewards: user_pend_reward,
-            }
-        );
+            },
+        )
+        .unwrap();
+        let rewards_response: AccruedRewardsResponse = from_binary(&res).unwrap();
+        assert_eq!(rewards_response.rewards[0].amount, Uint128::from(1382u128));
+        env.block.time = env.block.time.plus_seconds(50);
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AccruedRewards {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap();
+        let rewards_response: AccruedRewardsResponse = from_binary(&res).unwrap();
+        assert_eq!(rewards_response.rewards[0].amount, Uint128::from(2010u128));
     }
 
     #[test]
-    fn unbond_stake() {
-        let mut deps = mock_dependencies(&[Coin {
-            denom: DEFAULT_REWARD_DENOM.to_string(),
-            amount: Uint128::from(100u128),
-        }]);
+    fn compound_rewards() {
+        let mut deps = mock_dependencies(&[]);
 
         let init_msg = default_init();
         let env = mock_env();
-        let info = mock_info("addr0000", &[]);
+        let info = mock_info("owner", &[]);
         instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
 
-        let msg = ExecuteMsg::UnbondStake {
-            amount: Uint128::from(100u128),
-        };
-
-        // Failed underflow
+        // Failed rewards not paid in the staking token
+        let msg = ExecuteMsg::CompoundRewards {};
         let info = mock_info("addr0000", &[]);
-        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
         match res {
-            Err(StdError::GenericErr { msg, .. }) => {
-                assert_eq!(msg, "Decrease amount cannot exceed user balance: 0")
-            }
+            Err(ContractError::CompoundNotSupported {}) => {}
             _ => panic!("DO NOT ENTER HERE"),
-        };
+        }
+
+        let mut deps = mock_dependencies(&[]);
+        let mut init_msg = default_init();
+        init_msg.reward_token = Some(Addr::unchecked(MOCK_CW20_CONTRACT_ADDR));
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
 
         let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
         let receive_msg = receive_stake_msg("addr0000", 100);
         execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
 
-        // claimed_rewards = 100, total_balance = 100
-        // global_index == 1
+        // Failed no rewards
+        let msg = ExecuteMsg::CompoundRewards {};
         let info = mock_info("addr0000", &[]);
-        let msg = ExecuteMsg::UpdateGlobalIndex {};
-        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+        match res {
+            Err(ContractError::NoRewards {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+
+        let distribute_msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "lottery_contract".to_string(),
+            amount: Uint128::from(200u128),
+            msg: to_binary(&ReceiveMsg::DistributeRewards {}).unwrap(),
+        });
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        execute(deps.as_mut(), env.clone(), info, distribute_msg).unwrap();
 
         let info = mock_info("addr0000", &[]);
-        let msg = ExecuteMsg::UnbondStake {
-            amount: Uint128::from(100u128),
-        };
-        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "compound_rewards"),
+                attr("holder_address", "addr0000"),
+                attr("amount", "200"),
+            ]
+        );
 
         let res = query(
             deps.as_ref(),
@@ -352,34 +3159,50 @@ mod tests {
             },
         )
         .unwrap();
-
         let holder_response: HolderResponse = from_binary(&res).unwrap();
+        assert_eq!(holder_response.balance, Uint128::from(300u128));
+
+        let res = query(deps.as_ref(), env, QueryMsg::State {}).unwrap();
+        let state_response: StateResponse = from_binary(&res).unwrap();
         assert_eq!(
-            holder_response,
-            HolderResponse {
-                address: "addr0000".to_string(),
-                balance: Uint128::zero(),
-                index: Decimal::one(),
-                pending_rewards: Decimal::from_str("100").unwrap(),
+            state_response,
+            StateResponse {
+                total_balance: Uint128::from(300u128),
+                total_unbonding: Uint128::zero(),
+                reward_indices: vec![
+                    RewardIndex {
+                        denom: DEFAULT_REWARD_DENOM.to_string(),
+                        global_index: Decimal::zero(),
+                        prev_reward_balance: Uint128::zero(),
+                        undistributed_rewards: Uint128::zero(),
+                    },
+                    RewardIndex {
+                        denom: MOCK_CW20_CONTRACT_ADDR.to_string(),
+                        global_index: Decimal::from_ratio(2u128, 1u128),
+                        prev_reward_balance: Uint128::zero(),
+                        undistributed_rewards: Uint128::zero(),
+                    },
+                ],
             }
         );
     }
 
     #[test]
-    fn claim_rewards() {
+    fn withdraw_stake() {
         let mut deps = mock_dependencies(&[Coin {
             denom: DEFAULT_REWARD_DENOM.to_string(),
             amount: Uint128::from(100u128),
         }]);
 
         let init_msg = default_init();
-        let env = mock_env();
+        let mut env = mock_env();
         let info = mock_info(MOCK_TOKEN_CONTRACT_ADDR, &[]);
         instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
 
         let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
         let receive_msg = receive_stake_msg("addr0000", 100);
-        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+        execute(deps.as_mut(), env.clone(), info, receive_msg.clone()).unwrap();
+
         let res = query(
             deps.as_ref(),
             env.clone(),
@@ -395,8 +3218,11 @@ mod tests {
             HolderResponse {
                 address: "addr0000".to_string(),
                 balance: Uint128::from(100u128),
-                index: Decimal::zero(),
-                pending_rewards: Decimal::zero(),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: Decimal::zero(),
+                    pending_rewards: Decimal::zero(),
+                }],
             }
         );
 
@@ -409,7 +3235,6 @@ mod tests {
         let msg = ExecuteMsg::ClaimRewards { recipient: None };
         let info = mock_info("addr0000", &[]);
         let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-
         assert_eq!(
             res.messages[0],
             SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
@@ -417,129 +3242,188 @@ mod tests {
                 amount: vec![Coin {
                     denom: "uusd".to_string(),
                     amount: Uint128::from(99u128), // 1% tax
-                },]
+                }]
             }))
         );
 
-        // Set recipient
-        // claimed_rewards = 100, total_balance = 100
-        // global_index == 1
-        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
-        let msg = ExecuteMsg::UpdateGlobalIndex {};
+        // withdraw stake
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(100u128),
+        };
+        let info = mock_info("addr0000", &[]);
+        env.block.height = 5;
         execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
-        let msg = ExecuteMsg::ClaimRewards {
-            recipient: Some(Addr::unchecked("addr0001").to_string()),
+        // withdraw before unbonding fails
+        let msg = ExecuteMsg::WithdrawStake {
+            cap: None,
+            recipient: None,
+        };
+        let info = mock_info("addr0000", &[]);
+        env.block.height = 10;
+        let res = execute(deps.as_mut(), env.clone(), info, msg);
+
+        match res {
+            Err(ContractError::NothingToWithdraw {}) => {}
+            _ => panic!("Unexpected error"),
+        }
+
+        // withdraw works after unbonding period
+        let msg = ExecuteMsg::WithdrawStake {
+            cap: None,
+            recipient: None,
         };
         let info = mock_info("addr0000", &[]);
+        env.block.height = 10000;
         let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let cw20_transfer_msg = Cw20ExecuteMsg::Transfer {
+            recipient: "addr0000".to_string(),
+            amount: Uint128::from(100u128),
+        };
         assert_eq!(
             res.messages[0],
-            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-                to_address: "addr0001".to_string(),
-                amount: vec![Coin {
-                    denom: "uusd".to_string(),
-                    amount: Uint128::from(99u128), // 1% tax
-                },]
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CW20_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&cw20_transfer_msg).unwrap(),
+                funds: vec![]
             }))
         );
     }
 
     #[test]
-    fn withdraw_stake() {
-        let mut deps = mock_dependencies(&[Coin {
-            denom: DEFAULT_REWARD_DENOM.to_string(),
-            amount: Uint128::from(100u128),
-        }]);
+    fn withdraw_stake_unbonding_time() {
+        let mut deps = mock_dependencies(&[]);
 
-        let init_msg = default_init();
+        let mut init_msg = default_init();
+        init_msg.unbonding_period = Duration::Time(86400);
         let mut env = mock_env();
-        let info = mock_info(MOCK_TOKEN_CONTRACT_ADDR, &[]);
+        let info = mock_info("owner", &[]);
         instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
 
         let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
         let receive_msg = receive_stake_msg("addr0000", 100);
-        execute(deps.as_mut(), env.clone(), info, receive_msg.clone()).unwrap();
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
+
+        let msg = ExecuteMsg::UnbondStake {
+            amount: Uint128::from(100u128),
+        };
+        let info = mock_info("addr0000", &[]);
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
         let res = query(
             deps.as_ref(),
             env.clone(),
-            QueryMsg::Holder {
+            QueryMsg::Claims {
                 address: "addr0000".to_string(),
+                start_after: None,
+                limit: None,
             },
         )
         .unwrap();
-
-        let holder_response: HolderResponse = from_binary(&res).unwrap();
+        let claims_response: ClaimsResponse = from_binary(&res).unwrap();
         assert_eq!(
-            holder_response,
-            HolderResponse {
-                address: "addr0000".to_string(),
-                balance: Uint128::from(100u128),
-                index: Decimal::zero(),
-                pending_rewards: Decimal::zero(),
-            }
+            claims_response.claims,
+            vec![Claim {
+                id: 1,
+                amount: Uint128::from(100u128),
+                release_at: Expiration::AtTime(env.block.time.plus_seconds(86400)),
+                created_at_height: env.block.height,
+                created_at_time: Some(env.block.time),
+                unbonding_period: Duration::Time(86400),
+            }]
         );
 
-        // claimed_rewards = 100, total_balance = 100
-        // global_index == 1
-        let info = mock_info(MOCK_HUB_CONTRACT_ADDR, &[]);
-        let msg = ExecuteMsg::UpdateGlobalIndex {};
-        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        // Failed blocks passed but not the unbonding time
+        env.block.height += 100000;
+        env.block.time = env.block.time.plus_seconds(86399);
+        let msg = ExecuteMsg::WithdrawStake {
+            cap: None,
+            recipient: None,
+        };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+        match res {
+            Err(ContractError::NothingToWithdraw {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
+        }
 
-        let msg = ExecuteMsg::ClaimRewards { recipient: None };
+        env.block.time = env.block.time.plus_seconds(1);
         let info = mock_info("addr0000", &[]);
-        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let res = execute(deps.as_mut(), env, info, msg).unwrap();
         assert_eq!(
-            res.messages[0],
-            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-                to_address: "addr0000".to_string(),
-                amount: vec![Coin {
-                    denom: "uusd".to_string(),
-                    amount: Uint128::from(99u128), // 1% tax
-                }]
-            }))
+            res.messages,
+            vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CW20_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "addr0000".to_string(),
+                    amount: Uint128::from(100u128),
+                })
+                .unwrap(),
+                funds: vec![],
+            }))]
         );
+    }
+
+    #[test]
+    fn withdraw_stake_to_recipient() {
+        let mut deps = mock_dependencies(&[]);
+
+        let init_msg = default_init();
+        let mut env = mock_env();
+        let info = mock_info("owner", &[]);
+        instantiate(deps.as_mut(), env.clone(), info, init_msg).unwrap();
+
+        let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
+        let receive_msg = receive_stake_msg("addr0000", 100);
+        execute(deps.as_mut(), env.clone(), info, receive_msg).unwrap();
 
-        // withdraw stake
         let msg = ExecuteMsg::UnbondStake {
             amount: Uint128::from(100u128),
         };
         let info = mock_info("addr0000", &[]);
-        env.block.height = 5;
         execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        env.block.height += 1000;
 
-        // withdraw before unbonding fails
-        let msg = ExecuteMsg::WithdrawStake { cap: None };
+        // Failed invalid recipient
+        let msg = ExecuteMsg::WithdrawStake {
+            cap: None,
+            recipient: Some("a".to_string()),
+        };
         let info = mock_info("addr0000", &[]);
-        env.block.height = 10;
         let res = execute(deps.as_mut(), env.clone(), info, msg);
-
         match res {
-            Err(StdError::GenericErr { msg, .. }) => {
-                assert_eq!(msg, "Wait for the unbonding period")
-            }
-            _ => panic!("Unexpected error"),
+            Err(ContractError::Std(_)) => {}
+            _ => panic!("DO NOT ENTER HERE"),
         }
 
-        // withdraw works after unbonding period
-        let msg = ExecuteMsg::WithdrawStake { cap: None };
-        let info = mock_info("addr0000", &[]);
-        env.block.height = 10000;
-        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-
-        let cw20_transfer_msg = Cw20ExecuteMsg::Transfer {
-            recipient: "addr0000".to_string(),
-            amount: Uint128::from(100u128),
+        let msg = ExecuteMsg::WithdrawStake {
+            cap: None,
+            recipient: Some("cold0000".to_string()),
         };
+        let info = mock_info("addr0000", &[]);
+        let res = execute(deps.as_mut(), env, info, msg).unwrap();
         assert_eq!(
             res.messages[0],
             SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: MOCK_CW20_CONTRACT_ADDR.to_string(),
-                msg: to_binary(&cw20_transfer_msg).unwrap(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "cold0000".to_string(),
+                    amount: Uint128::from(100u128),
+                })
+                .unwrap(),
                 funds: vec![]
             }))
         );
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "withdraw_stake"),
+                attr("holder_address", "addr0000"),
+                attr("recipient", "cold0000"),
+                attr("amount", "100"),
+            ]
+        );
     }
 
     #[test]
@@ -573,8 +3457,11 @@ mod tests {
             HolderResponse {
                 address: "addr0000".to_string(),
                 balance: Uint128::from(100u128),
-                index: Decimal::zero(),
-                pending_rewards: Decimal::zero(),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: Decimal::zero(),
+                    pending_rewards: Decimal::zero(),
+                }],
             }
         );
 
@@ -606,23 +3493,55 @@ mod tests {
         env.block.height = 5;
         execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
-        // cap is less then release, wait for more to unbond
+        // cap is less then release, the claim is paid partially
         let msg = ExecuteMsg::WithdrawStake {
-            cap: Some(Uint128::from(50u128)),
+            cap: Some(Uint128::from(30u128)),
+            recipient: None,
         };
         let info = mock_info("addr0000", &[]);
         env.block.height = 100000;
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            res.messages[0],
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CW20_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "addr0000".to_string(),
+                    amount: Uint128::from(30u128),
+                })
+                .unwrap(),
+                funds: vec![]
+            }))
+        );
+
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Claims {
+                address: "addr0000".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let claims_response: ClaimsResponse = from_binary(&res).unwrap();
+        assert_eq!(claims_response.claims[0].amount, Uint128::from(70u128));
+
+        // Failed zero cap with mature claims
+        let msg = ExecuteMsg::WithdrawStake {
+            cap: Some(Uint128::zero()),
+            recipient: None,
+        };
+        let info = mock_info("addr0000", &[]);
         let res = execute(deps.as_mut(), env.clone(), info, msg);
         match res {
-            Err(StdError::GenericErr { msg, .. }) => {
-                assert_eq!(msg, "Wait for the unbonding period")
-            }
-
-            _ => panic!("Unexpected error"),
+            Err(ContractError::ZeroCap {}) => {}
+            _ => panic!("DO NOT ENTER HERE"),
         }
 
         let msg = ExecuteMsg::WithdrawStake {
             cap: Some(Uint128::from(150u128)),
+            recipient: None,
         };
         let info = mock_info("addr0000", &[]);
         env.block.height = 100000;
@@ -630,7 +3549,7 @@ mod tests {
 
         let cw20_transfer_msg = Cw20ExecuteMsg::Transfer {
             recipient: "addr0000".to_string(),
-            amount: Uint128::from(100u128),
+            amount: Uint128::from(70u128),
         };
         assert_eq!(
             res.messages[0],
@@ -672,8 +3591,11 @@ mod tests {
             HolderResponse {
                 address: "addr0000".to_string(),
                 balance: Uint128::from(11u128),
-                index: Decimal::zero(),
-                pending_rewards: Decimal::zero(),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: Decimal::zero(),
+                    pending_rewards: Decimal::zero(),
+                }],
             }
         );
 
@@ -715,8 +3637,11 @@ mod tests {
             HolderResponse {
                 address: "addr0000".to_string(),
                 balance: Uint128::from(11u128),
-                index,
-                pending_rewards: Decimal::from_str("0.999999999999999991").unwrap(),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index,
+                    pending_rewards: Decimal::from_str("0.999999999999999991").unwrap(),
+                }],
             }
         );
 
@@ -725,9 +3650,14 @@ mod tests {
         assert_eq!(
             state_response,
             StateResponse {
-                global_index: index,
                 total_balance: Uint128::from(11u128),
-                prev_reward_balance: Uint128::from(1u128)
+                total_unbonding: Uint128::zero(),
+                reward_indices: vec![RewardIndex {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    global_index: index,
+                    prev_reward_balance: Uint128::from(1u128),
+                    undistributed_rewards: Uint128::zero(),
+                }],
             }
         );
     }
@@ -748,6 +3678,7 @@ mod tests {
         let receive_msg = receive_stake_msg(Addr::unchecked("addr0000").as_str(), 100);
         execute(deps.as_mut(), env.clone(), info, receive_msg.clone()).unwrap();
 
+        // the rewards held before addr0000 bonded are indexed, global_index == 1
         let info = mock_info(MOCK_CW20_CONTRACT_ADDR, &[]);
         let receive_msg = receive_stake_msg(Addr::unchecked("addr0001").as_str(), 200);
         execute(deps.as_mut(), env.clone(), info, receive_msg.clone()).unwrap();
@@ -773,20 +3704,29 @@ mod tests {
                     HolderResponse {
                         address: String::from("addr0000"),
                         balance: Uint128::from(100u128),
-                        index: Decimal::zero(),
-                        pending_rewards: Decimal::zero(),
+                        rewards: vec![HolderReward {
+                            denom: DEFAULT_REWARD_DENOM.to_string(),
+                            index: Decimal::zero(),
+                            pending_rewards: Decimal::zero(),
+                        }],
                     },
                     HolderResponse {
                         address: String::from("addr0001"),
                         balance: Uint128::from(200u128),
-                        index: Decimal::zero(),
-                        pending_rewards: Decimal::zero(),
+                        rewards: vec![HolderReward {
+                            denom: DEFAULT_REWARD_DENOM.to_string(),
+                            index: Decimal::one(),
+                            pending_rewards: Decimal::zero(),
+                        }],
                     },
                     HolderResponse {
                         address: String::from("addr0002"),
                         balance: Uint128::from(300u128),
-                        index: Decimal::zero(),
-                        pending_rewards: Decimal::zero(),
+                        rewards: vec![HolderReward {
+                            denom: DEFAULT_REWARD_DENOM.to_string(),
+                            index: Decimal::one(),
+                            pending_rewards: Decimal::zero(),
+                        }],
                     }
                 ],
             }
@@ -809,8 +3749,11 @@ mod tests {
                 holders: vec![HolderResponse {
                     address: String::from("addr0000"),
                     balance: Uint128::from(100u128),
-                    index: Decimal::zero(),
-                    pending_rewards: Decimal::zero(),
+                    rewards: vec![HolderReward {
+                        denom: DEFAULT_REWARD_DENOM.to_string(),
+                        index: Decimal::zero(),
+                        pending_rewards: Decimal::zero(),
+                    }],
                 }],
             }
         );
@@ -845,8 +3788,11 @@ mod tests {
                 holders: vec![HolderResponse {
                     address: String::from("addr0001"),
                     balance: Uint128::from(200u128),
-                    index: Decimal::zero(),
-                    pending_rewards: Decimal::zero(),
+                    rewards: vec![HolderReward {
+                        denom: DEFAULT_REWARD_DENOM.to_string(),
+                        index: Decimal::one(),
+                        pending_rewards: Decimal::zero(),
+                    }],
                 }],
             }
         );
@@ -856,7 +3802,7 @@ mod tests {
     fn proper_prev_balance() {
         let mut deps = mock_dependencies(&[Coin {
             denom: DEFAULT_REWARD_DENOM.to_string(),
-            amount: Uint128::from(100u128),
+            amount: Uint128::from(677101666827000000u128),
         }]);
 
         let init_msg = default_init();
@@ -877,17 +3823,25 @@ mod tests {
             .save(
                 deps.as_mut().storage,
                 &State {
-                    global_index,
                     total_balance: all_balance,
-                    prev_reward_balance: rewards,
+                    total_unbonding: Uint128::zero(),
+                    reward_indices: vec![RewardIndex {
+                        denom: DEFAULT_REWARD_DENOM.to_string(),
+                        global_index,
+                        prev_reward_balance: rewards,
+                        undistributed_rewards: Uint128::zero(),
+                    }],
                 },
             )
             .unwrap();
 
         let holder = Holder {
             balance: amount1,
-            index: Decimal::from_str("0").unwrap(),
-            pending_rewards: Decimal::from_str("0").unwrap(),
+            rewards: vec![HolderReward {
+                denom: DEFAULT_REWARD_DENOM.to_string(),
+                index: Decimal::from_str("0").unwrap(),
+                pending_rewards: Decimal::from_str("0").unwrap(),
+            }],
         };
         store_holder(
             &mut deps.storage,
@@ -898,8 +3852,11 @@ mod tests {
 
         let holder = Holder {
             balance: amount2,
-            index: Decimal::from_str("0").unwrap(),
-            pending_rewards: Decimal::from_str("0").unwrap(),
+            rewards: vec![HolderReward {
+                denom: DEFAULT_REWARD_DENOM.to_string(),
+                index: Decimal::from_str("0").unwrap(),
+                pending_rewards: Decimal::from_str("0").unwrap(),
+            }],
         };
         store_holder(
             &mut deps.storage,
@@ -910,8 +3867,11 @@ mod tests {
 
         let holder = Holder {
             balance: amount3,
-            index: Decimal::from_str("0").unwrap(),
-            pending_rewards: Decimal::from_str("0").unwrap(),
+            rewards: vec![HolderReward {
+                denom: DEFAULT_REWARD_DENOM.to_string(),
+                index: Decimal::from_str("0").unwrap(),
+                pending_rewards: Decimal::from_str("0").unwrap(),
+            }],
         };
         store_holder(
             &mut deps.storage,
@@ -923,23 +3883,31 @@ mod tests {
         let msg = ExecuteMsg::ClaimRewards { recipient: None };
         let info = mock_info("addr0000", &[]);
         execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        pay_rewards(&mut deps);
 
         let msg = ExecuteMsg::ClaimRewards { recipient: None };
         let info = mock_info("addr0001", &[]);
         execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        pay_rewards(&mut deps);
 
         let msg = ExecuteMsg::ClaimRewards { recipient: None };
         let info = mock_info("addr0002", &[]);
         execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        pay_rewards(&mut deps);
 
         let res = query(deps.as_ref(), env.clone(), QueryMsg::State {}).unwrap();
         let state_response: StateResponse = from_binary(&res).unwrap();
         assert_eq!(
             state_response,
             StateResponse {
-                global_index,
                 total_balance: all_balance,
-                prev_reward_balance: Uint128::from(1u128)
+                total_unbonding: Uint128::zero(),
+                reward_indices: vec![RewardIndex {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    global_index,
+                    prev_reward_balance: Uint128::from(1u128),
+                    undistributed_rewards: Uint128::zero(),
+                }],
             }
         );
         let res = query(
@@ -957,8 +3925,11 @@ mod tests {
             HolderResponse {
                 address: "addr0000".to_string(),
                 balance: amount1,
-                index: global_index,
-                pending_rewards: Decimal::from_str("0.212799238975421283").unwrap(),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: global_index,
+                    pending_rewards: Decimal::from_str("0.212799238975421283").unwrap(),
+                }],
             }
         );
 
@@ -976,8 +3947,11 @@ mod tests {
             HolderResponse {
                 address: "addr0001".to_string(),
                 balance: amount2,
-                index: global_index,
-                pending_rewards: Decimal::from_str("0.078595712259178717").unwrap(),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: global_index,
+                    pending_rewards: Decimal::from_str("0.078595712259178717").unwrap(),
+                }],
             }
         );
 
@@ -995,8 +3969,11 @@ mod tests {
             HolderResponse {
                 address: "addr0002".to_string(),
                 balance: amount3,
-                index: global_index,
-                pending_rewards: Decimal::from_str("0.701700000000000000").unwrap(),
+                rewards: vec![HolderReward {
+                    denom: DEFAULT_REWARD_DENOM.to_string(),
+                    index: global_index,
+                    pending_rewards: Decimal::from_str("0.701700000000000000").unwrap(),
+                }],
             }
         );
     }
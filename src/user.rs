@@ -1,73 +1,179 @@
-use crate::state::{read_holder, read_holders, store_holder, Config, Holder, State, CONFIG, STATE};
+use crate::state::{
+    read_holder, read_holders, reward_token_denom, store_holder, Config, Holder, HolderReward,
+    State, CONFIG, MEMBERS, STAKED_BALANCES, STATE, TOTAL_STAKED, TOTAL_WEIGHT,
+};
 
 use cosmwasm_std::{
-    from_binary, to_binary, BankMsg, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, MessageInfo,
-    Response, StdError, StdResult, Uint128, WasmMsg,
+    from_binary, to_binary, Addr, Api, BankMsg, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env,
+    MessageInfo, Response, StdError, StdResult, Storage, Uint128, Uint64, WasmMsg,
 };
 
-use crate::claim::{claim_tokens, create_claim};
+use crate::claim::{claim_tokens, create_claim, rebond_claims};
+use crate::error::ContractError;
+use crate::global::{
+    add_rewards, coins_to_string, handle_distribute_rewards, release_streamed_rewards,
+    update_global_index,
+};
+use crate::group::member_weight;
 use crate::math::{
     decimal_multiplication_in_256, decimal_subtraction_in_256, decimal_summation_in_256,
 };
-use crate::msg::{AccruedRewardsResponse, HolderResponse, HoldersResponse, ReceiveMsg};
+use crate::msg::{
+    AccruedRewardsResponse, HolderResponse, HoldersResponse, Operation, ReceiveMsg,
+    StakedBalanceAtHeightResponse, TotalStakedAtHeightResponse,
+};
+use crate::pause::assert_not_paused;
 use crate::taxation::deduct_tax;
-use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Expiration};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
 use cw4::Member;
 use std::str::FromStr;
 
 pub fn handle_claim_rewards(
-    deps: DepsMut,
-    _env: Env,
+    mut deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     recipient: Option<String>,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let holder_addr = info.sender;
-    let holder_addr_raw = deps.api.addr_canonicalize(&holder_addr.as_str())?;
+    let holder_addr_raw = deps.api.addr_canonicalize(holder_addr.as_str())?;
 
     let recipient = match recipient {
-        Some(value) => deps.api.addr_validate(value.as_str()).unwrap(),
+        Some(value) => deps.api.addr_validate(value.as_str())?,
         None => holder_addr.clone(),
     };
 
     let mut holder: Holder = read_holder(deps.storage, &holder_addr_raw)?;
     let mut state: State = STATE.load(deps.storage)?;
-    let config: Config = CONFIG.load(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let reward_token = reward_token_denom(deps.api, &config)?;
 
-    let reward_with_decimals =
-        calculate_decimal_rewards(state.global_index, holder.index, holder.balance)?;
+    update_global_index(deps.branch(), &env, &config, &mut state)?;
+    update_holder_rewards(&state, &mut holder)?;
 
-    let all_reward_with_decimals =
-        decimal_summation_in_256(reward_with_decimals, holder.pending_rewards);
-    let decimals = get_decimals(all_reward_with_decimals).unwrap();
+    let mut rewards: Vec<Coin> = vec![];
+    for reward_index in state.reward_indices.iter_mut() {
+        let holder_reward = holder_reward_mut(&mut holder, &reward_index.denom);
+        let amount = holder_reward.pending_rewards * Uint128::from(1u128);
+        if amount.is_zero() {
+            continue;
+        }
 
-    let rewards = all_reward_with_decimals * Uint128::from(1u128);
+        // keep the decimals as pending rewards
+        holder_reward.pending_rewards = get_decimals(holder_reward.pending_rewards)?;
+        reward_index.prev_reward_balance = reward_index.prev_reward_balance.checked_sub(amount)?;
+        rewards.push(Coin {
+            denom: reward_index.denom.clone(),
+            amount,
+        });
+    }
 
-    if rewards.is_zero() {
-        return Err(StdError::generic_err("No rewards have accrued yet"));
+    if rewards.is_empty() {
+        return Err(ContractError::NoRewards {});
     }
-    //let f = state.prev_reward_balance.wrapping_sub(rewards);
-    let new_balance = (state.prev_reward_balance.checked_sub(rewards))?;
-    state.prev_reward_balance = new_balance;
     STATE.save(deps.storage, &state)?;
-
-    holder.pending_rewards = decimals;
-    holder.index = state.global_index;
     store_holder(deps.storage, &holder_addr_raw, &holder)?;
 
-    Ok(Response::new()
-        .add_message(CosmosMsg::Bank(BankMsg::Send {
+    let mut res = Response::new();
+
+    // bank coins must be sorted by denom
+    rewards.sort_by(|a, b| a.denom.cmp(&b.denom));
+    let mut amount: Vec<Coin> = vec![];
+    for coin in rewards.iter() {
+        if Some(&coin.denom) == reward_token.as_ref() {
+            res = res.add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: coin.denom.clone(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount: coin.amount,
+                })?,
+                funds: vec![],
+            }));
+            continue;
+        }
+
+        let coin = deduct_tax(&deps.querier, coin.clone())?;
+        if !coin.amount.is_zero() {
+            amount.push(coin);
+        }
+    }
+
+    if !amount.is_empty() {
+        res = res.add_message(CosmosMsg::Bank(BankMsg::Send {
             to_address: recipient.to_string(),
-            amount: vec![deduct_tax(
-                &deps.querier,
-                Coin {
-                    denom: config.reward_denom,
-                    amount: rewards,
-                },
-            )?],
-        }))
+            amount,
+        }));
+    }
+
+    Ok(res
         .add_attribute("action", "claim_reward")
         .add_attribute("holder_address", holder_addr)
-        .add_attribute("rewards", rewards))
+        .add_attribute("rewards", coins_to_string(&rewards)))
+}
+
+pub fn handle_compound_rewards(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    // rewards can only be bonded when they are paid in the staking token
+    if config.reward_token.as_ref() != Some(&config.cw20_token_addr) {
+        return Err(ContractError::CompoundNotSupported {});
+    }
+    if !info.funds.is_empty() {
+        return Err(ContractError::FundsNotAllowed {});
+    }
+
+    let address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let token_denom = deps.api.addr_humanize(&config.cw20_token_addr)?.to_string();
+
+    let mut state: State = STATE.load(deps.storage)?;
+    let mut holder: Holder = read_holder(deps.storage, &address_raw)?;
+
+    update_global_index(deps.branch(), &env, &config, &mut state)?;
+    update_holder_rewards(&state, &mut holder)?;
+
+    let holder_reward = holder_reward_mut(&mut holder, &token_denom);
+    let amount = holder_reward.pending_rewards * Uint128::from(1u128);
+    if amount.is_zero() {
+        return Err(ContractError::NoRewards {});
+    }
+    // keep the decimals as pending rewards
+    holder_reward.pending_rewards = get_decimals(holder_reward.pending_rewards)?;
+
+    // the rewards stay in the contract as bonded stake
+    let reward_index = state
+        .reward_indices
+        .iter_mut()
+        .find(|reward_index| reward_index.denom == token_denom)
+        .ok_or(ContractError::CompoundNotSupported {})?;
+    reward_index.prev_reward_balance = reward_index.prev_reward_balance.checked_sub(amount)?;
+    holder.balance += amount;
+    state.total_balance += amount;
+
+    let exec_msg = update_member_msg(
+        deps.storage,
+        deps.api,
+        &config,
+        env.block.height,
+        info.sender.to_string(),
+        holder.balance,
+    )?;
+    store_holder(deps.storage, &address_raw, &holder)?;
+    STATE.save(deps.storage, &state)?;
+    STAKED_BALANCES.save(
+        deps.storage,
+        address_raw.as_slice(),
+        &holder.balance,
+        env.block.height,
+    )?;
+    TOTAL_STAKED.save(deps.storage, &state.total_balance, env.block.height)?;
+
+    Ok(Response::new()
+        .add_messages(exec_msg)
+        .add_attribute("action", "compound_rewards")
+        .add_attribute("holder_address", info.sender.as_str())
+        .add_attribute("amount", amount))
 }
 
 pub fn handle_receive(
@@ -75,130 +181,386 @@ pub fn handle_receive(
     env: Env,
     info: MessageInfo,
     wrapper: Cw20ReceiveMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
 
-    // only loterra cw20 contract can send receieve msg
-    if info.sender != deps.api.addr_humanize(&config.cw20_token_addr)? {
-        return Err(StdError::generic_err(
-            "only loterra contract can send receive messages",
-        ));
-    }
-
     let msg: ReceiveMsg = from_binary(&wrapper.msg)?;
     match msg {
-        ReceiveMsg::BondStake {} => handle_bond(deps, env, info, wrapper.sender, wrapper.amount),
+        ReceiveMsg::BondStake { recipient } => {
+            // only loterra cw20 contract can send receieve msg
+            if info.sender != deps.api.addr_humanize(&config.cw20_token_addr)? {
+                return Err(ContractError::InvalidCw20Token {});
+            }
+            assert_not_paused(deps.storage, Operation::Bond)?;
+            let holder_addr = match recipient {
+                Some(recipient) => deps.api.addr_validate(&recipient)?.to_string(),
+                None => wrapper.sender.clone(),
+            };
+            handle_bond(deps, env, info, wrapper.sender, holder_addr, wrapper.amount)
+        }
+        ReceiveMsg::DistributeRewards {} => {
+            // only the cw20 reward token can be distributed
+            if Some(info.sender.to_string()) != reward_token_denom(deps.api, &config)? {
+                return Err(ContractError::InvalidRewardToken {});
+            }
+            handle_distribute_rewards(deps, env, wrapper.amount)
+        }
     }
 }
 
 pub fn handle_bond(
-    deps: DepsMut,
-    _env: Env,
+    mut deps: DepsMut,
+    env: Env,
     info: MessageInfo,
+    funder: String,
     holder_addr: String,
     amount: Uint128,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
     if !info.funds.is_empty() {
-        return Err(StdError::generic_err("Do not send funds with stake"));
+        return Err(ContractError::FundsNotAllowed {});
     }
     if amount.is_zero() {
-        return Err(StdError::generic_err("Amount required"));
+        return Err(ContractError::ZeroAmount {});
     }
 
-    let address_raw = deps.api.addr_canonicalize(&holder_addr.as_str())?;
+    let address_raw = deps.api.addr_canonicalize(holder_addr.as_str())?;
 
     let mut state: State = STATE.load(deps.storage)?;
     let mut holder: Holder = read_holder(deps.storage, &address_raw)?;
 
-    // get decimals
-    let rewards = calculate_decimal_rewards(state.global_index, holder.index, holder.balance)?;
-
-    holder.index = state.global_index;
-    holder.pending_rewards = decimal_summation_in_256(rewards, holder.pending_rewards);
+    update_global_index(deps.branch(), &env, &config, &mut state)?;
+    update_holder_rewards(&state, &mut holder)?;
     holder.balance += amount;
     state.total_balance += amount;
 
-    let msg = cw4_group::msg::ExecuteMsg::UpdateMembers {
-        remove: vec![],
-        add: vec![Member {
-            addr: holder_addr.clone(),
-            weight: holder.balance.u128() as u64,
-        }],
-    };
-    let exec_msg = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: deps.api.addr_humanize(&config.group_addr)?.to_string(),
-        msg: to_binary(&msg)?,
-        funds: vec![],
-    });
+    let exec_msg = update_member_msg(
+        deps.storage,
+        deps.api,
+        &config,
+        env.block.height,
+        holder_addr.clone(),
+        holder.balance,
+    )?;
     store_holder(deps.storage, &address_raw, &holder)?;
     STATE.save(deps.storage, &state)?;
+    STAKED_BALANCES.save(
+        deps.storage,
+        address_raw.as_slice(),
+        &holder.balance,
+        env.block.height,
+    )?;
+    TOTAL_STAKED.save(deps.storage, &state.total_balance, env.block.height)?;
 
     Ok(Response::new()
-        .add_message(exec_msg)
+        .add_messages(exec_msg)
         .add_attribute("action", "bond_stake")
+        .add_attribute("funder", funder)
         .add_attribute("holder_address", holder_addr.as_str())
-        .add_attribute("amount", &amount.to_string()))
+        .add_attribute("amount", amount.to_string()))
 }
 
 pub fn handle_unbound(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     amount: Uint128,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
 
-    let address_raw = deps.api.addr_canonicalize(&info.sender.as_str())?;
+    let address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
 
     if !info.funds.is_empty() {
-        return Err(StdError::generic_err("Do not send funds with stake"));
+        return Err(ContractError::FundsNotAllowed {});
     }
     if amount.is_zero() {
-        return Err(StdError::generic_err("Amount required"));
+        return Err(ContractError::ZeroAmount {});
     }
 
     let mut state: State = STATE.load(deps.storage)?;
     let mut holder: Holder = read_holder(deps.storage, &address_raw)?;
     if holder.balance < amount {
-        return Err(StdError::generic_err(format!(
-            "Decrease amount cannot exceed user balance: {}",
-            holder.balance
-        )));
+        return Err(ContractError::InsufficientStake {
+            available: holder.balance,
+        });
     }
 
-    let rewards = calculate_decimal_rewards(state.global_index, holder.index, holder.balance)?;
-
-    holder.index = state.global_index;
-    holder.pending_rewards = decimal_summation_in_256(rewards, holder.pending_rewards);
+    update_global_index(deps.branch(), &env, &config, &mut state)?;
+    update_holder_rewards(&state, &mut holder)?;
     holder.balance = holder.balance.checked_sub(amount)?;
     state.total_balance = state.total_balance.checked_sub(amount)?;
+    state.total_unbonding += amount;
 
-    let msg = cw4_group::msg::ExecuteMsg::UpdateMembers {
-        remove: vec![],
-        add: vec![Member {
-            addr: info.sender.to_string(),
-            weight: holder.balance.u128() as u64,
-        }],
-    };
-    let exec_msg = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: deps.api.addr_humanize(&config.group_addr)?.to_string(),
-        msg: to_binary(&msg)?,
-        funds: vec![],
-    });
+    let exec_msg = update_member_msg(
+        deps.storage,
+        deps.api,
+        &config,
+        env.block.height,
+        info.sender.to_string(),
+        holder.balance,
+    )?;
 
     store_holder(deps.storage, &address_raw, &holder)?;
     STATE.save(deps.storage, &state)?;
+    STAKED_BALANCES.save(
+        deps.storage,
+        address_raw.as_slice(),
+        &holder.balance,
+        env.block.height,
+    )?;
+    TOTAL_STAKED.save(deps.storage, &state.total_balance, env.block.height)?;
 
     // create claim
-    let release_height = Expiration::AtHeight(env.block.height + config.unbonding_period);
-    create_claim(deps.storage, address_raw, amount, release_height)?;
+    let claim_id = create_claim(
+        deps.storage,
+        address_raw,
+        amount,
+        &env.block,
+        config.unbonding_period,
+        config.max_claims,
+    )?;
 
     Ok(Response::new()
-        .add_message(exec_msg)
+        .add_messages(exec_msg)
         .add_attribute("action", "unbond_stake")
         .add_attribute("holder_address", info.sender.as_str())
-        .add_attribute("amount", &amount.to_string()))
+        .add_attribute("amount", amount.to_string())
+        .add_attribute("claim_id", claim_id.to_string()))
+}
+
+pub fn handle_instant_unbond(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let penalty_rate = config
+        .instant_unbond_penalty
+        .ok_or(ContractError::InstantUnbondDisabled {})?;
+
+    let address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let cw20_human_addr = deps.api.addr_humanize(&config.cw20_token_addr)?;
+
+    if !info.funds.is_empty() {
+        return Err(ContractError::FundsNotAllowed {});
+    }
+    if amount.is_zero() {
+        return Err(ContractError::ZeroAmount {});
+    }
+
+    let mut state: State = STATE.load(deps.storage)?;
+    let mut holder: Holder = read_holder(deps.storage, &address_raw)?;
+    if holder.balance < amount {
+        return Err(ContractError::InsufficientStake {
+            available: holder.balance,
+        });
+    }
+
+    // the penalty is rounded up, amount - floor(amount * (1 - rate)) = ceil(amount * rate)
+    let payout = amount * decimal_subtraction_in_256(Decimal::one(), penalty_rate);
+    let penalty = amount.checked_sub(payout)?;
+    // without a treasury the penalty is redistributed through the staking token index
+    let redistribute = !penalty.is_zero() && config.treasury.is_none();
+    if redistribute && config.reward_token.as_ref() != Some(&config.cw20_token_addr) {
+        return Err(ContractError::NoPenaltyRecipient {});
+    }
+
+    update_global_index(deps.branch(), &env, &config, &mut state)?;
+    update_holder_rewards(&state, &mut holder)?;
+    holder.balance = holder.balance.checked_sub(amount)?;
+    state.total_balance = state.total_balance.checked_sub(amount)?;
+
+    let mut res = Response::new().add_messages(update_member_msg(
+        deps.storage,
+        deps.api,
+        &config,
+        env.block.height,
+        info.sender.to_string(),
+        holder.balance,
+    )?);
+
+    if redistribute {
+        // the penalty stays in the contract as rewards of the remaining stakers
+        let total_balance = state.total_balance;
+        let reward_index = state
+            .reward_indices
+            .iter_mut()
+            .find(|reward_index| reward_index.denom == cw20_human_addr.as_str())
+            .ok_or(ContractError::NoPenaltyRecipient {})?;
+        reward_index.prev_reward_balance += penalty;
+        add_rewards(
+            deps.storage,
+            config.distribution_period,
+            total_balance,
+            reward_index,
+            penalty,
+            env.block.time.seconds(),
+        )?;
+    } else if let Some(treasury) = config.treasury.as_ref().filter(|_| !penalty.is_zero()) {
+        res = res.add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: cw20_human_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: deps.api.addr_humanize(treasury)?.to_string(),
+                amount: penalty,
+            })?,
+            funds: vec![],
+        }));
+    }
+
+    store_holder(deps.storage, &address_raw, &holder)?;
+    STATE.save(deps.storage, &state)?;
+    STAKED_BALANCES.save(
+        deps.storage,
+        address_raw.as_slice(),
+        &holder.balance,
+        env.block.height,
+    )?;
+    TOTAL_STAKED.save(deps.storage, &state.total_balance, env.block.height)?;
+
+    let payout = amount.checked_sub(penalty)?;
+    if !payout.is_zero() {
+        res = res.add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: cw20_human_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: info.sender.to_string(),
+                amount: payout,
+            })?,
+            funds: vec![],
+        }));
+    }
+
+    Ok(res
+        .add_attribute("action", "instant_unbond")
+        .add_attribute("holder_address", info.sender.as_str())
+        .add_attribute("amount", amount)
+        .add_attribute("penalty", penalty))
+}
+
+pub fn handle_rebond(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if !info.funds.is_empty() {
+        return Err(ContractError::FundsNotAllowed {});
+    }
+    if amount == Some(Uint128::zero()) {
+        return Err(ContractError::ZeroAmount {});
+    }
+
+    let address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    let rebonded = rebond_claims(deps.storage, address_raw.clone(), &env.block, amount)?;
+
+    let mut state: State = STATE.load(deps.storage)?;
+    let mut holder: Holder = read_holder(deps.storage, &address_raw)?;
+
+    update_global_index(deps.branch(), &env, &config, &mut state)?;
+    update_holder_rewards(&state, &mut holder)?;
+    holder.balance += rebonded;
+    state.total_balance += rebonded;
+    state.total_unbonding = state.total_unbonding.checked_sub(rebonded)?;
+
+    let exec_msg = update_member_msg(
+        deps.storage,
+        deps.api,
+        &config,
+        env.block.height,
+        info.sender.to_string(),
+        holder.balance,
+    )?;
+    store_holder(deps.storage, &address_raw, &holder)?;
+    STATE.save(deps.storage, &state)?;
+    STAKED_BALANCES.save(
+        deps.storage,
+        address_raw.as_slice(),
+        &holder.balance,
+        env.block.height,
+    )?;
+    TOTAL_STAKED.save(deps.storage, &state.total_balance, env.block.height)?;
+
+    Ok(Response::new()
+        .add_messages(exec_msg)
+        .add_attribute("action", "rebond_stake")
+        .add_attribute("holder_address", info.sender.as_str())
+        .add_attribute("amount", rebonded))
+}
+
+pub fn handle_transfer_stake(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if !info.funds.is_empty() {
+        return Err(ContractError::FundsNotAllowed {});
+    }
+    if amount.is_zero() {
+        return Err(ContractError::ZeroAmount {});
+    }
+
+    let recipient = deps.api.addr_validate(recipient.as_str())?;
+    if recipient == info.sender {
+        return Err(ContractError::SelfTransfer {});
+    }
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let recipient_raw = deps.api.addr_canonicalize(recipient.as_str())?;
+
+    let mut state: State = STATE.load(deps.storage)?;
+    let mut sender: Holder = read_holder(deps.storage, &sender_raw)?;
+    if sender.balance < amount {
+        return Err(ContractError::InsufficientStake {
+            available: sender.balance,
+        });
+    }
+    let mut receiver: Holder = read_holder(deps.storage, &recipient_raw)?;
+
+    // both holders keep the rewards accrued on their balance until now
+    update_global_index(deps.branch(), &env, &config, &mut state)?;
+    update_holder_rewards(&state, &mut sender)?;
+    update_holder_rewards(&state, &mut receiver)?;
+    sender.balance = sender.balance.checked_sub(amount)?;
+    receiver.balance += amount;
+
+    let exec_msg = update_members_msg(
+        deps.storage,
+        deps.api,
+        &config,
+        env.block.height,
+        vec![
+            (info.sender.to_string(), sender.balance),
+            (recipient.to_string(), receiver.balance),
+        ],
+    )?;
+
+    store_holder(deps.storage, &sender_raw, &sender)?;
+    store_holder(deps.storage, &recipient_raw, &receiver)?;
+    STATE.save(deps.storage, &state)?;
+    STAKED_BALANCES.save(
+        deps.storage,
+        sender_raw.as_slice(),
+        &sender.balance,
+        env.block.height,
+    )?;
+    STAKED_BALANCES.save(
+        deps.storage,
+        recipient_raw.as_slice(),
+        &receiver.balance,
+        env.block.height,
+    )?;
+
+    Ok(Response::new()
+        .add_messages(exec_msg)
+        .add_attribute("action", "transfer_stake")
+        .add_attribute("holder_address", info.sender.as_str())
+        .add_attribute("recipient", recipient.as_str())
+        .add_attribute("amount", amount))
 }
 
 pub fn handle_withdraw_stake(
@@ -206,19 +568,29 @@ pub fn handle_withdraw_stake(
     env: Env,
     info: MessageInfo,
     cap: Option<Uint128>,
-) -> StdResult<Response> {
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    let address_raw = deps.api.addr_canonicalize(&info.sender.as_str())?;
+    let address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    let recipient = match recipient {
+        Some(value) => deps.api.addr_validate(value.as_str())?,
+        None => info.sender.clone(),
+    };
 
     let amount = claim_tokens(deps.storage, address_raw, &env.block, cap)?;
     if amount.is_zero() {
-        return Err(StdError::generic_err("Wait for the unbonding period"));
+        return Err(ContractError::NothingToWithdraw {});
     }
 
+    let mut state: State = STATE.load(deps.storage)?;
+    state.total_unbonding = state.total_unbonding.checked_sub(amount)?;
+    STATE.save(deps.storage, &state)?;
+
     let cw20_human_addr = deps.api.addr_humanize(&config.cw20_token_addr)?;
 
     let cw20_transfer_msg = Cw20ExecuteMsg::Transfer {
-        recipient: info.sender.to_string(),
+        recipient: recipient.to_string(),
         amount,
     };
     Ok(Response::new()
@@ -229,19 +601,31 @@ pub fn handle_withdraw_stake(
         }))
         .add_attribute("action", "withdraw_stake")
         .add_attribute("holder_address", info.sender.as_str())
+        .add_attribute("recipient", recipient.as_str())
         .add_attribute("amount", amount))
 }
 
-pub fn query_accrued_rewards(deps: Deps, address: String) -> StdResult<AccruedRewardsResponse> {
-    let global_index = STATE.load(deps.storage)?.global_index;
-
-    let holder: Holder = read_holder(deps.storage, &deps.api.addr_canonicalize(&address)?)?;
-    let reward_with_decimals =
-        calculate_decimal_rewards(global_index, holder.index, holder.balance)?;
-    let all_reward_with_decimals =
-        decimal_summation_in_256(reward_with_decimals, holder.pending_rewards);
-
-    let rewards = all_reward_with_decimals * Uint128::from(1u128);
+pub fn query_accrued_rewards(
+    deps: Deps,
+    env: Env,
+    address: String,
+) -> StdResult<AccruedRewardsResponse> {
+    let mut state: State = STATE.load(deps.storage)?;
+    // include the rewards streamed since the last update
+    release_streamed_rewards(deps.storage, &mut state, env.block.time.seconds())?;
+
+    let mut holder: Holder = read_holder(deps.storage, &deps.api.addr_canonicalize(&address)?)?;
+    update_holder_rewards(&state, &mut holder)?;
+
+    let rewards = state
+        .reward_indices
+        .iter()
+        .map(|reward_index| Coin {
+            denom: reward_index.denom.clone(),
+            amount: holder_reward_mut(&mut holder, &reward_index.denom).pending_rewards
+                * Uint128::from(1u128),
+        })
+        .collect();
 
     Ok(AccruedRewardsResponse { rewards })
 }
@@ -251,11 +635,36 @@ pub fn query_holder(deps: Deps, address: String) -> StdResult<HolderResponse> {
     Ok(HolderResponse {
         address,
         balance: holder.balance,
-        index: holder.index,
-        pending_rewards: holder.pending_rewards,
+        rewards: holder.rewards,
     })
 }
 
+pub fn query_staked_balance_at_height(
+    deps: Deps,
+    env: Env,
+    address: String,
+    height: Option<u64>,
+) -> StdResult<StakedBalanceAtHeightResponse> {
+    let address_raw = deps.api.addr_canonicalize(&address)?;
+    let height = height.unwrap_or(env.block.height);
+    let balance = STAKED_BALANCES
+        .may_load_at_height(deps.storage, address_raw.as_slice(), height)?
+        .unwrap_or_default();
+    Ok(StakedBalanceAtHeightResponse { balance, height })
+}
+
+pub fn query_total_staked_at_height(
+    deps: Deps,
+    env: Env,
+    height: Option<u64>,
+) -> StdResult<TotalStakedAtHeightResponse> {
+    let height = height.unwrap_or(env.block.height);
+    let total = TOTAL_STAKED
+        .may_load_at_height(deps.storage, height)?
+        .unwrap_or_default();
+    Ok(TotalStakedAtHeightResponse { total, height })
+}
+
 pub fn query_holders(
     deps: Deps,
     start_after: Option<String>,
@@ -272,6 +681,98 @@ pub fn query_holders(
     Ok(HoldersResponse { holders })
 }
 
+// set the holder's weight in the cw4 group to the weight of the bonded balance
+fn update_member_msg(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    config: &Config,
+    height: u64,
+    address: String,
+    balance: Uint128,
+) -> StdResult<Option<CosmosMsg>> {
+    update_members_msg(storage, api, config, height, vec![(address, balance)])
+}
+
+// store the weight of every address from its new staked balance and set it in the group
+// in a single message, the weights and their total are stored even without a group.
+// Addresses without weight are removed from the group, as SyncGroup does.
+fn update_members_msg(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    config: &Config,
+    height: u64,
+    balances: Vec<(String, Uint128)>,
+) -> StdResult<Option<CosmosMsg>> {
+    let mut total_weight = Uint64::from(TOTAL_WEIGHT.load(storage)?);
+    let mut add = vec![];
+    let mut remove = vec![];
+    for (address, balance) in balances {
+        let addr = Addr::unchecked(address);
+        let prev_weight = MEMBERS.may_load(storage, &addr)?.unwrap_or_default();
+        let weight = member_weight(&config.weight_function, balance)?;
+        total_weight = total_weight
+            .checked_sub(Uint64::from(prev_weight))?
+            .checked_add(Uint64::from(weight))?;
+        if weight == 0 {
+            MEMBERS.remove(storage, &addr, height)?;
+            remove.push(addr.to_string());
+        } else {
+            MEMBERS.save(storage, &addr, &weight, height)?;
+            add.push(Member {
+                addr: addr.to_string(),
+                weight,
+            });
+        }
+    }
+    TOTAL_WEIGHT.save(storage, &total_weight.u64())?;
+
+    let group_addr = match config.group_addr.as_ref() {
+        Some(group_addr) => group_addr,
+        None => return Ok(None),
+    };
+    let msg = cw4_group::msg::ExecuteMsg::UpdateMembers { remove, add };
+    Ok(Some(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: api.addr_humanize(group_addr)?.to_string(),
+        msg: to_binary(&msg)?,
+        funds: vec![],
+    })))
+}
+
+// move the rewards accrued since the holder's last update to pending rewards
+// and set the holder's index of every reward denom to the global index.
+fn update_holder_rewards(state: &State, holder: &mut Holder) -> StdResult<()> {
+    let balance = holder.balance;
+    for reward_index in state.reward_indices.iter() {
+        let holder_reward = holder_reward_mut(holder, &reward_index.denom);
+        let rewards =
+            calculate_decimal_rewards(reward_index.global_index, holder_reward.index, balance)?;
+
+        holder_reward.index = reward_index.global_index;
+        holder_reward.pending_rewards =
+            decimal_summation_in_256(rewards, holder_reward.pending_rewards);
+    }
+    Ok(())
+}
+
+// get the holder's rewards of a denom, a denom the holder never accrued starts at a zero index.
+fn holder_reward_mut<'a>(holder: &'a mut Holder, denom: &str) -> &'a mut HolderReward {
+    match holder
+        .rewards
+        .iter()
+        .position(|reward| reward.denom == denom)
+    {
+        Some(position) => &mut holder.rewards[position],
+        None => {
+            holder.rewards.push(HolderReward {
+                denom: denom.to_string(),
+                index: Decimal::zero(),
+                pending_rewards: Decimal::zero(),
+            });
+            holder.rewards.last_mut().unwrap()
+        }
+    }
+}
+
 // calculate the reward based on the sender's index and the global index.
 fn calculate_decimal_rewards(
     global_index: Decimal,
@@ -287,12 +788,12 @@ fn calculate_decimal_rewards(
 
 // calculate the reward with decimal
 fn get_decimals(value: Decimal) -> StdResult<Decimal> {
-    let stringed: &str = &*value.to_string();
-    let parts: &[&str] = &*stringed.split('.').collect::<Vec<&str>>();
+    let stringed: &str = &value.to_string();
+    let parts: &[&str] = &stringed.split('.').collect::<Vec<&str>>();
     match parts.len() {
         1 => Ok(Decimal::zero()),
         2 => {
-            let decimals = Decimal::from_str(&*("0.".to_owned() + parts[1]))?;
+            let decimals = Decimal::from_str(&("0.".to_owned() + parts[1]))?;
             Ok(decimals)
         }
         _ => Err(StdError::generic_err("Unexpected number of dots")),